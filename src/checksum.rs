@@ -0,0 +1,67 @@
+// ┌───────────────────────────────────────────────────────────────────────────┐
+// │                                                                           │
+// │  ██████╗ ██████╗  ██████╗   Copyright (C) The Prospective Company         │
+// │  ██╔══██╗██╔══██╗██╔═══██╗  All Rights Reserved - April 2022              │
+// │  ██████╔╝██████╔╝██║   ██║                                                │
+// │  ██╔═══╝ ██╔══██╗██║   ██║  Proprietary and confidential. Unauthorized    │
+// │  ██║     ██║  ██║╚██████╔╝  copying of this file, via any medium is       │
+// │  ╚═╝     ╚═╝  ╚═╝ ╚═════╝   strictly prohibited.                          │
+// │                                                                           │
+// └───────────────────────────────────────────────────────────────────────────┘
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+/// The algorithm a checksum was computed with. It's stored alongside the hash
+/// so the algorithm can change without migrating the tracking table.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ChecksumAlgo {
+    Sha256,
+    /// Any other algorithm, identified by its name.
+    Custom(String),
+}
+
+impl ChecksumAlgo {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ChecksumAlgo::Sha256 => "sha256",
+            ChecksumAlgo::Custom(name) => name,
+        }
+    }
+}
+
+/// Checksum of a migration's contents, used to detect migrations that
+/// were edited after they had been applied.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    pub algo: ChecksumAlgo,
+    pub bytes: Vec<u8>,
+}
+
+impl Checksum {
+    pub fn new(algo: ChecksumAlgo, bytes: Vec<u8>) -> Self {
+        Self { algo, bytes }
+    }
+
+    /// SHA-256 of `data`.
+    pub fn sha256(data: impl AsRef<[u8]>) -> Self {
+        Self::new(ChecksumAlgo::Sha256, Sha256::digest(data).to_vec())
+    }
+
+    /// Parse the `algo:hex` form the checksum is stored in.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (algo, bytes) = s.split_once(':')?;
+        let algo = match algo {
+            "sha256" => ChecksumAlgo::Sha256,
+            other => ChecksumAlgo::Custom(other.to_string()),
+        };
+        Some(Self::new(algo, hex::decode(bytes).ok()?))
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algo.as_str(), hex::encode(&self.bytes))
+    }
+}
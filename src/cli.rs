@@ -9,20 +9,382 @@
 // │                                                                           │
 // └───────────────────────────────────────────────────────────────────────────┘
 
-use crate::Migrator;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 
-use crate::error::Result;
-use clap::{Parser, Subcommand};
+use crate::{term, LockStrategy, Migrator, PreflightCheck};
+
+use crate::error::{self, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
-use prettytable::{format, row, Table};
+use futures_util::future::{select, Either};
+use prettytable::{format, row, Cell, Table};
 
 #[derive(Debug, Parser)]
 #[clap(about = "Promad migration tool")]
 pub struct PromadCli {
+    #[clap(
+        long,
+        global = true,
+        help = "Log every SQL statement executed while migrating"
+    )]
+    pub verbose: bool,
+    #[clap(long, global = true, help = "URL of the database to migrate")]
+    pub database_url: Option<String>,
+    #[clap(long, global = true, help = "Table applied migrations are tracked in")]
+    pub table_name: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        help = "How to keep concurrent migrators apart"
+    )]
+    pub lock: Option<CliLock>,
+    #[clap(
+        long,
+        global = true,
+        help = "Don't check the applied history against the local migrations (for emergencies)"
+    )]
+    pub no_validate: bool,
+    #[clap(long, global = true, help = "File to read PROMAD_* settings from")]
+    pub config: Option<PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        help = "Directory to load .up.sql/.down.sql migrations from [default: ./migrations]"
+    )]
+    pub migrations_dir: Option<PathBuf>,
     #[clap(subcommand)]
     pub subcmd: PromadSubcommand,
 }
 
+impl PromadCli {
+    /// Resolve the settings from the flags, then the environment, then the
+    /// config file given by `--config` or `PROMAD_CONFIG`.
+    pub fn config(&self) -> Result<CliConfig> {
+        self.config_with_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Like `config`, but reading the variables from `var` rather than the
+    /// environment.
+    pub fn config_with_vars(&self, var: impl Fn(&str) -> Option<String>) -> Result<CliConfig> {
+        let path = self
+            .config
+            .clone()
+            .or_else(|| var("PROMAD_CONFIG").map(PathBuf::from));
+        let file = match path {
+            Some(path) => CliConfig::from_file(&path)?,
+            None => CliConfig::default(),
+        };
+        Ok(self.flags().or(CliConfig::from_vars(var)?).or(file))
+    }
+
+    /// The settings given as flags.
+    fn flags(&self) -> CliConfig {
+        CliConfig {
+            database_url: self.database_url.clone(),
+            table_name: self.table_name.clone(),
+            lock: self.lock,
+        }
+    }
+
+    /// Apply the global flags to the migrator and execute the subcommand,
+    /// then close the migrator's pool since the CLI is done with it.
+    /// Use `interpreter` instead to keep the pool open.
+    ///
+    /// The migrator is already connected, so `--database-url` is an error.
+    /// `--table-name` and `--lock` are applied to it, but the environment
+    /// and config file aren't read.
+    ///
+    /// SQL migrations are loaded from `--migrations-dir` first. Without the
+    /// flag `./migrations` is loaded, but only if it exists and the
+    /// migrator doesn't have migrations of its own.
+    pub async fn run<DB>(self, migrator: Migrator<DB>) -> Result<()>
+    where
+        DB: sqlx::Database,
+        for<'c> &'c mut <DB as sqlx::Database>::Connection: sqlx::Executor<'c, Database = DB>,
+        for<'q> <DB as sqlx::database::HasArguments<'q>>::Arguments: sqlx::IntoArguments<'q, DB>,
+    {
+        if self.database_url.is_some() {
+            return Err(error::Error::ConfigError(
+                "--database-url can't be used with a migrator that's already connected".to_string(),
+            ));
+        }
+        self.run_connected(migrator).await
+    }
+
+    /// `run` for a migrator connected to the configured database, such as
+    /// one built by `CliConfig::migrator`.
+    async fn run_connected<DB>(self, migrator: Migrator<DB>) -> Result<()>
+    where
+        DB: sqlx::Database,
+        for<'c> &'c mut <DB as sqlx::Database>::Connection: sqlx::Executor<'c, Database = DB>,
+        for<'q> <DB as sqlx::database::HasArguments<'q>>::Arguments: sqlx::IntoArguments<'q, DB>,
+    {
+        let mut migrator = self.flags().configure(migrator);
+        match &self.migrations_dir {
+            Some(dir) => migrator.add_migrations_from_dir(dir)?,
+            None => {
+                let dir = Path::new("migrations");
+                if dir.is_dir() && migrator.migration_count() == 0 {
+                    migrator.add_migrations_from_dir(dir)?;
+                }
+            }
+        }
+        if self.no_validate {
+            eprintln!(
+                "{} Skipping validation of the migration history. The database may not match the local migrations.",
+                "WARNING:".red().bold()
+            );
+        }
+        let pool = migrator.pool.clone();
+        let migrator = migrator
+            .with_sql_logging(self.verbose)
+            .with_skip_validation(self.no_validate);
+        let res = interpreter(self.subcmd, migrator).await;
+        if let Some(pool) = pool {
+            pool.close().await;
+        }
+        res
+    }
+
+    /// Like `run`, but for a binary's `main`: print the error, if any, and
+    /// exit with the code `Error::exit_code` picks for it.
+    pub async fn run_and_exit<DB>(self, migrator: Migrator<DB>) -> !
+    where
+        DB: sqlx::Database,
+        for<'c> &'c mut <DB as sqlx::Database>::Connection: sqlx::Executor<'c, Database = DB>,
+        for<'q> <DB as sqlx::database::HasArguments<'q>>::Arguments: sqlx::IntoArguments<'q, DB>,
+    {
+        match self.run(migrator).await {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("{} {}", "error:".red().bold(), e);
+                std::process::exit(e.exit_code())
+            }
+        }
+    }
+}
+
+/// Lock strategies that can be picked from the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CliLock {
+    Advisory,
+    None,
+}
+
+impl From<CliLock> for LockStrategy {
+    fn from(lock: CliLock) -> Self {
+        match lock {
+            CliLock::Advisory => LockStrategy::default(),
+            CliLock::None => LockStrategy::None,
+        }
+    }
+}
+
+/// Settings an embedding binary needs to build a migrator. Each source
+/// leaves unset what it doesn't specify so they can be layered with `or`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CliConfig {
+    pub database_url: Option<String>,
+    pub table_name: Option<String>,
+    pub lock: Option<CliLock>,
+}
+
+impl CliConfig {
+    /// Read `PROMAD_DATABASE_URL`, `PROMAD_TABLE_NAME` and `PROMAD_LOCK`.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Read the same keys as `from_env` from `KEY=VALUE` lines in a file.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            error::Error::ConfigError(format!("can't read {}: {e}", path.display()))
+        })?;
+        let mut vars = std::collections::HashMap::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                error::Error::ConfigError(format!(
+                    "expected KEY=VALUE in {}: {line}",
+                    path.display()
+                ))
+            })?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|x| x.strip_suffix('"'))
+                .unwrap_or(value);
+            vars.insert(key.trim().to_string(), value.to_string());
+        }
+        Self::from_vars(|key| vars.get(key).cloned())
+    }
+
+    /// Read the same keys as `from_env` from `var`, such as a lookup in a
+    /// map of variables.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let lock = var("PROMAD_LOCK")
+            .map(|x| {
+                CliLock::from_str(&x, true)
+                    .map_err(|_| error::Error::ConfigError(format!("unknown PROMAD_LOCK: {x}")))
+            })
+            .transpose()?;
+        Ok(Self {
+            database_url: var("PROMAD_DATABASE_URL"),
+            table_name: var("PROMAD_TABLE_NAME"),
+            lock,
+        })
+    }
+
+    /// Fill in whatever isn't set here from `fallback`.
+    pub fn or(self, fallback: CliConfig) -> CliConfig {
+        CliConfig {
+            database_url: self.database_url.or(fallback.database_url),
+            table_name: self.table_name.or(fallback.table_name),
+            lock: self.lock.or(fallback.lock),
+        }
+    }
+
+    /// Apply the table name and lock strategy to a migrator.
+    pub fn configure<DB: sqlx::Database>(&self, mut migrator: Migrator<DB>) -> Migrator<DB> {
+        if let Some(table_name) = &self.table_name {
+            migrator = migrator.with_table_name(table_name);
+        }
+        if let Some(lock) = self.lock {
+            migrator = migrator.with_lock_strategy(lock.into());
+        }
+        migrator
+    }
+
+    /// Connect to the database and build a configured migrator.
+    #[cfg(feature = "postgres")]
+    pub async fn migrator(&self) -> Result<Migrator<sqlx::Postgres>> {
+        match AnyMigrator::from_config(self).await? {
+            AnyMigrator::Postgres(migrator) => Ok(migrator),
+            #[cfg(feature = "any")]
+            AnyMigrator::Any(_) => Err(error::Error::UnsupportedDatabaseScheme(
+                scheme(self.database_url.as_deref().unwrap_or_default()).to_string(),
+            )),
+        }
+    }
+}
+
+/// Fail on URLs for databases promad can't migrate. Only the scheme goes
+/// in the error so credentials don't end up in logs.
+#[cfg(any(feature = "postgres", feature = "any"))]
+fn check_scheme(url: &str) -> Result<()> {
+    let scheme = scheme(url);
+    if cfg!(feature = "postgres") && matches!(scheme, "postgres" | "postgresql") {
+        return Ok(());
+    }
+    Err(error::Error::UnsupportedDatabaseScheme(scheme.to_string()))
+}
+
+/// The scheme of a database URL, such as `postgres`.
+#[cfg(any(feature = "postgres", feature = "any"))]
+fn scheme(url: &str) -> &str {
+    url.split_once(':').map_or("", |(scheme, _)| scheme)
+}
+
+/// A migrator for whichever database a URL points at, so one binary can
+/// migrate any supported database. Postgres gets its own repo. With the
+/// `any` feature MySQL and SQLite are migrated through `sqlx::Any`, as long
+/// as sqlx's driver for them is enabled too. Needs the `postgres` or `any`
+/// feature, since otherwise there's no database to migrate.
+#[cfg(any(feature = "postgres", feature = "any"))]
+// Only one is ever built, so boxing the larger migrator would gain nothing.
+#[allow(clippy::large_enum_variant)]
+#[non_exhaustive]
+pub enum AnyMigrator {
+    #[cfg(feature = "postgres")]
+    Postgres(Migrator<sqlx::Postgres>),
+    #[cfg(feature = "any")]
+    Any(Migrator<sqlx::Any>),
+}
+
+#[cfg(any(feature = "postgres", feature = "any"))]
+impl AnyMigrator {
+    /// Connect to `url` with the backend its scheme names.
+    pub async fn connect(url: &str) -> Result<Self> {
+        #[cfg(feature = "any")]
+        if matches!(scheme(url), "mysql" | "mariadb" | "sqlite") {
+            sqlx::any::install_default_drivers();
+            let pool = sqlx::AnyPool::connect(url).await?;
+            return Ok(AnyMigrator::Any(Migrator::create(pool)));
+        }
+        check_scheme(url)?;
+        #[cfg(feature = "postgres")]
+        {
+            let pool = sqlx::PgPool::connect(url).await?;
+            Ok(AnyMigrator::Postgres(Migrator::create(pool)))
+        }
+        #[cfg(not(feature = "postgres"))]
+        unreachable!("check_scheme rejects every URL without the postgres feature")
+    }
+
+    /// Connect to the configured database and apply the rest of the
+    /// configuration.
+    pub async fn from_config(config: &CliConfig) -> Result<Self> {
+        let url = config.database_url.as_deref().ok_or_else(|| {
+            error::Error::ConfigError(
+                "no database URL, pass --database-url or set PROMAD_DATABASE_URL".to_string(),
+            )
+        })?;
+        Ok(match Self::connect(url).await? {
+            #[cfg(feature = "postgres")]
+            AnyMigrator::Postgres(migrator) => AnyMigrator::Postgres(config.configure(migrator)),
+            #[cfg(feature = "any")]
+            AnyMigrator::Any(migrator) => AnyMigrator::Any(config.configure(migrator)),
+        })
+    }
+
+    /// Register the SQL migrations anywhere under `dir`. See
+    /// `Migrator::add_migrations_from_dir`.
+    pub fn add_migrations_from_dir(&mut self, dir: &Path) -> Result<()> {
+        match self {
+            #[cfg(feature = "postgres")]
+            AnyMigrator::Postgres(migrator) => migrator.add_migrations_from_dir(dir),
+            #[cfg(feature = "any")]
+            AnyMigrator::Any(migrator) => migrator.add_migrations_from_dir(dir),
+        }
+    }
+
+    /// Run the command against whichever database this is. See
+    /// `PromadCli::run`.
+    pub async fn run(self, cli: PromadCli) -> Result<()> {
+        match self {
+            #[cfg(feature = "postgres")]
+            AnyMigrator::Postgres(migrator) => cli.run_connected(migrator).await,
+            #[cfg(feature = "any")]
+            AnyMigrator::Any(migrator) => cli.run_connected(migrator).await,
+        }
+    }
+}
+
+/// Connect to `database_url`, add `migrations` and run the command, for
+/// binaries that don't need to build the pool themselves. Everything else
+/// is configured like `CliConfig::migrator`, with `database_url` in place
+/// of the configured URL.
+#[cfg(feature = "postgres")]
+pub async fn run(
+    cli: PromadCli,
+    database_url: &str,
+    migrations: Vec<Box<dyn crate::Migration<sqlx::Postgres>>>,
+) -> Result<()> {
+    check_scheme(database_url)?;
+    let config = CliConfig {
+        database_url: Some(database_url.to_string()),
+        ..cli.config()?
+    };
+    let mut migrator = config.migrator().await?;
+    migrator.add_migrations(migrations)?;
+    cli.run_connected(migrator).await
+}
+
 /// The subcommands of the migration CLI.
 /// This can be embedded in other CLI tools so that
 /// users can include migration commands in their server
@@ -33,66 +395,453 @@ pub enum PromadSubcommand {
     Apply {
         #[clap(help = "The name of the migrations to apply to (inclusive)")]
         name: Option<String>,
+        #[clap(
+            long,
+            conflicts_with = "name",
+            help = "The number of pending migrations to apply"
+        )]
+        steps: Option<usize>,
+        #[clap(
+            long,
+            help = "Apply the migrations in a single transaction, so either all or none are applied"
+        )]
+        atomic: bool,
+        #[clap(
+            long,
+            conflicts_with_all = ["name", "steps", "atomic"],
+            help = "Apply the pending migrations timestamped at or before this RFC 3339 time"
+        )]
+        until: Option<chrono::DateTime<chrono::Utc>>,
+        #[clap(
+            long,
+            requires = "name",
+            conflicts_with_all = ["steps", "atomic", "until"],
+            help = "Record the named migration as applied without running it, for changes already made by hand"
+        )]
+        fake: bool,
     },
     #[clap(about = "Revert up to a specific migrations")]
     Revert {
-        #[clap(help = "The name of the migrations to revert to (inclusive)")]
-        name: String,
+        #[clap(
+            required_unless_present = "steps",
+            help = "The name of the migrations to revert to (inclusive)"
+        )]
+        name: Option<String>,
+        #[clap(
+            long,
+            conflicts_with = "name",
+            help = "The number of applied migrations to revert"
+        )]
+        steps: Option<usize>,
+        #[clap(long, help = "Also revert migrations marked as irreversible")]
+        force: bool,
     },
     #[clap(about = "Revert all migrations")]
-    RevertAll,
+    RevertAll {
+        #[clap(long, help = "Also revert migrations marked as irreversible")]
+        force: bool,
+    },
+    #[clap(about = "Drop the migrations table, reverting all migrations first")]
+    Reset {
+        #[clap(long, help = "Don't ask for confirmation")]
+        yes: bool,
+        #[clap(
+            long,
+            help = "Drop the migrations table without reverting the migrations"
+        )]
+        no_revert: bool,
+        #[clap(long, help = "Also revert migrations marked as irreversible")]
+        force: bool,
+    },
     #[clap(about = "List all changes")]
-    List,
+    List {
+        #[clap(long, value_enum, default_value_t = ListFormat::Table, help = "How to print the list")]
+        format: ListFormat,
+    },
+    #[clap(about = "Update stored checksums computed with a different algorithm")]
+    Restamp,
+    #[clap(about = "Record a baseline migration in place of the applied ones it replaces")]
+    Squash {
+        #[clap(help = "The last applied migration the baseline replaces")]
+        name: String,
+    },
+    #[clap(about = "Show every attempt to run a migration, including failed ones")]
+    History,
+    #[clap(about = "Print the SQL a migration runs without running it")]
+    Show {
+        #[clap(help = "The name of the migration to show")]
+        name: String,
+    },
+    #[clap(about = "Check that the database is ready to be migrated")]
+    Doctor,
+    #[clap(about = "Show how many migrations are pending")]
+    Status,
+    #[clap(about = "Print a hash of the applied migrations, to compare environments")]
+    Fingerprint,
+    #[cfg(feature = "serde")]
+    #[clap(about = "Write the migration history to a JSON file")]
+    Export {
+        #[clap(help = "The file to write")]
+        path: PathBuf,
+    },
+    #[cfg(feature = "serde")]
+    #[clap(
+        about = "Record the migration history from a file written by export, without running anything"
+    )]
+    Import {
+        #[clap(help = "The file to read")]
+        path: PathBuf,
+        #[clap(long, help = "Replace the history if there already is one")]
+        force: bool,
+    },
+}
+
+/// Output formats of the `list` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    Table,
+    /// `name,applied,applied_at,ordering_key` with a header row.
+    Csv,
+}
+
+/// Quote a CSV field if it contains anything that would break the row.
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+/// Table format shared by the commands that print tables.
+fn list_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator('|')
+        .borders(' ')
+        .separators(
+            &[format::LinePosition::Title],
+            format::LineSeparator::new('-', '+', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
 }
 
 /// Execute the subcommand given a migrator.
+///
+/// Ctrl-C while applying or reverting drops the migration in progress,
+/// which rolls back its transaction, and lists the migrations that
+/// completed before returning `Error::Interrupted`.
 pub async fn interpreter<DB: sqlx::Database>(
     subcmd: PromadSubcommand,
     migrator: Migrator<DB>,
 ) -> Result<()> {
+    let symbols = term::symbols();
+    let migrator = match &subcmd {
+        PromadSubcommand::Revert { force, .. }
+        | PromadSubcommand::RevertAll { force }
+        | PromadSubcommand::Reset { force, .. } => migrator.with_force_irreversible(*force),
+        _ => migrator,
+    };
+    if !matches!(
+        subcmd,
+        PromadSubcommand::Apply { .. }
+            | PromadSubcommand::Revert { .. }
+            | PromadSubcommand::RevertAll { .. }
+    ) {
+        return dispatch(subcmd, &migrator).await;
+    }
+
+    // The tracking table may not exist yet, in which case nothing is applied.
+    let before = applied_names(&migrator).await.unwrap_or_default();
+    // Boxed rather than pinned on the stack so it can be dropped before the
+    // history is read below.
+    let op = Box::pin(dispatch(subcmd, &migrator));
+    let ctrl_c = std::pin::pin!(tokio::signal::ctrl_c());
+    match select(op, ctrl_c).await {
+        Either::Left((res, _)) => return res,
+        // Without a signal handler there is nothing to race against.
+        Either::Right((Err(_), op)) => return op.await,
+        // Rolls back the migration in progress and frees its connections,
+        // along with the lock.
+        Either::Right((Ok(()), op)) => drop(op),
+    }
+    eprintln!("{}", "Interrupted, rolled back".bold().yellow());
+
+    // What completed can't be told without the history, but the run was
+    // still interrupted.
+    let Ok(after) = applied_names(&migrator).await else {
+        return Err(error::Error::Interrupted { completed: vec![] });
+    };
+    let completed: Vec<String> = after.symmetric_difference(&before).cloned().collect();
+    for name in &completed {
+        let verb = if after.contains(name) {
+            "applied"
+        } else {
+            "reverted"
+        };
+        eprintln!("{} {} {}", symbols.ok.bold().green(), name, verb);
+    }
+    Err(error::Error::Interrupted { completed })
+}
+
+/// The pending migrations `apply` would run given `name` or `steps`, for
+/// applying them as a group.
+async fn atomic_group<DB: sqlx::Database>(
+    migrator: &Migrator<DB>,
+    name: Option<String>,
+    steps: Option<usize>,
+) -> Result<Vec<String>> {
+    let mut migrations = migrator.list_migrations().await?;
+    if let Some(name) = name {
+        let target = migrations
+            .iter()
+            .position(|x| migrator.normalize_name(&x.name) == migrator.normalize_name(&name))
+            .ok_or(error::Error::NoSuchMigration(name))?;
+        migrations.truncate(target + 1);
+    }
+    Ok(migrations
+        .into_iter()
+        .filter(|x| x.run_at.is_none())
+        .take(steps.unwrap_or(usize::MAX))
+        .map(|x| x.name.into_owned())
+        .collect())
+}
+
+/// Ask on the terminal before `reset` destroys anything.
+fn confirm_reset(table_name: &str, revert: bool) -> bool {
+    if revert {
+        eprintln!("This reverts every applied migration and drops {table_name}.");
+    } else {
+        eprintln!("This drops {table_name}, leaving the migrated schema in place.");
+    }
+    eprint!("Type 'yes' to continue: ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && answer.trim() == "yes"
+}
+
+/// Names of the migrations applied to the database.
+async fn applied_names<DB: sqlx::Database>(migrator: &Migrator<DB>) -> Result<BTreeSet<String>> {
+    Ok(migrator
+        .db_migrations()
+        .await?
+        .into_iter()
+        .map(|row| row.name)
+        .collect())
+}
+
+async fn dispatch<DB: sqlx::Database>(
+    subcmd: PromadSubcommand,
+    migrator: &Migrator<DB>,
+) -> Result<()> {
+    let symbols = term::symbols();
     match subcmd {
-        PromadSubcommand::Apply { name } => match name {
-            Some(name) => {
+        PromadSubcommand::Apply {
+            name: Some(name),
+            fake: true,
+            ..
+        } => {
+            migrator.apply_fake(&name).await?;
+            eprintln!(
+                "{} Recorded {name} as applied without running it. The database must already have its changes.",
+                "WARNING:".red().bold()
+            );
+        }
+        PromadSubcommand::Apply {
+            name,
+            steps,
+            atomic: true,
+            ..
+        } => {
+            let group = atomic_group(migrator, name, steps).await?;
+            let group = group.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+            migrator.apply_group(&group).await?;
+        }
+        PromadSubcommand::Apply {
+            until: Some(until), ..
+        } => {
+            migrator.apply_until_timestamp(until).await?;
+        }
+        PromadSubcommand::Apply { name, steps, .. } => match (name, steps) {
+            (Some(name), _) => {
                 migrator.apply_to_inclusive(&name).await?;
             }
-            None => {
+            (None, Some(steps)) => {
+                migrator.apply_n(steps).await?;
+            }
+            (None, None) => {
                 migrator.apply_all().await?;
             }
         },
-        PromadSubcommand::Revert { name } => {
-            migrator.revert_to_inclusive(&name).await?;
+        PromadSubcommand::Revert { name, steps, .. } => match (name, steps) {
+            (Some(name), _) => {
+                migrator.revert_to_inclusive(&name).await?;
+            }
+            (None, Some(steps)) => {
+                migrator.revert_n(steps).await?;
+            }
+            (None, None) => unreachable!("clap requires a name or steps"),
+        },
+        PromadSubcommand::List {
+            format: ListFormat::Csv,
+        } => {
+            println!("name,applied,applied_at,ordering_key");
+            for row in migrator.list_migrations().await? {
+                println!(
+                    "{},{},{},{}",
+                    csv_field(&row.name),
+                    row.run_at.is_some(),
+                    row.run_at.map(|x| x.to_rfc3339()).unwrap_or_default(),
+                    row.ordering_key
+                );
+            }
         }
-        PromadSubcommand::List => {
+        PromadSubcommand::List {
+            format: ListFormat::Table,
+        } => {
+            let migrations = migrator.list_migrations().await?;
+            // The column is only shown if some migration has a description.
+            let described = migrations.iter().any(|x| x.description.is_some());
             let mut table = Table::new();
-            let format = format::FormatBuilder::new()
-                .column_separator('|')
-                .borders(' ')
-                .separators(
-                    &[format::LinePosition::Title],
-                    format::LineSeparator::new('-', '+', ' ', ' '),
-                )
-                .padding(1, 1)
-                .build();
-            table.set_format(format);
-            table.set_titles(row!["Name", "Ran", "Run Time"]);
-            migrator.list_migrations().await?.iter().for_each(|row| {
-                table.add_row(row![
+            table.set_format(list_format());
+            let mut titles = row!["Name", "Ran", "Run Time", "Applied By"];
+            if described {
+                titles.add_cell(Cell::new("Description"));
+            }
+            table.set_titles(titles);
+            migrations.iter().for_each(|row| {
+                let mut cells = row![
                     row.name.bold(),
                     if row.run_at.is_some() {
-                        "✓".bold().green()
+                        symbols.ok.bold().green()
                     } else {
-                        "✗".bold().dimmed()
+                        symbols.no.bold().dimmed()
                     },
-                    row.run_at.map(|x| x.to_string()).unwrap_or_default()
-                ]);
+                    row.run_at.map(|x| x.to_string()).unwrap_or_default(),
+                    row.applied_by.clone().unwrap_or_default()
+                ];
+                if described {
+                    cells.add_cell(Cell::new(row.description.as_deref().unwrap_or_default()));
+                }
+                table.add_row(cells);
             });
 
             // Print the table to stdout
             table.printstd();
         }
-        PromadSubcommand::RevertAll => {
+        PromadSubcommand::Doctor => {
+            let mut failed = None;
+            for check in PreflightCheck::ALL {
+                if failed.is_some() {
+                    println!("{} {}", "-".dimmed(), check.to_string().dimmed());
+                    continue;
+                }
+                match migrator.preflight_check(check).await {
+                    Ok(()) => println!("{} {} OK", symbols.ok.bold().green(), check),
+                    Err(e) => {
+                        println!("{} {}: {}", symbols.fail.bold().red(), check, e);
+                        failed = Some(error::Error::PreflightFailed {
+                            check,
+                            source: Box::new(e),
+                        });
+                    }
+                }
+            }
+            if let Some(e) = failed {
+                return Err(e);
+            }
+        }
+        PromadSubcommand::Squash { name } => {
+            migrator.squash(&name).await?;
+        }
+        PromadSubcommand::Fingerprint => {
+            println!("{}", migrator.schema_fingerprint().await?);
+        }
+        PromadSubcommand::Status => {
+            // Both counts leave out the migrations the tag filter excludes.
+            let pending = migrator.pending_count().await?;
+            println!(
+                "{} pending, {} applied",
+                pending,
+                migrator.selected_migrations().count() - pending
+            );
+        }
+        PromadSubcommand::History => {
+            let mut table = Table::new();
+            table.set_format(list_format());
+            table.set_titles(row!["Name", "Direction", "Started", "Finished", "Result"]);
+            for attempt in migrator.attempts().await? {
+                let result = match (&attempt.finished_at, &attempt.error) {
+                    (_, Some(error)) => format!("{} {error}", symbols.fail.bold().red()),
+                    (Some(_), None) => symbols.ok.bold().green().to_string(),
+                    (None, None) => symbols.unknown.bold().dimmed().to_string(),
+                };
+                table.add_row(row![
+                    attempt.name.bold(),
+                    attempt.direction,
+                    attempt.started_at.to_string(),
+                    attempt
+                        .finished_at
+                        .map(|x| x.to_string())
+                        .unwrap_or_default(),
+                    result
+                ]);
+            }
+            table.printstd();
+        }
+        PromadSubcommand::Show { name } => {
+            let rendered = migrator.render(&name)?;
+            println!("{}\n{}", "-- up".dimmed(), rendered.up_sql.trim_end());
+            match rendered.down_sql {
+                Some(down_sql) => println!("\n{}\n{}", "-- down".dimmed(), down_sql.trim_end()),
+                None => println!("\n{}", "-- down (irreversible)".dimmed()),
+            }
+        }
+        PromadSubcommand::Restamp => {
+            migrator.restamp().await?;
+        }
+        PromadSubcommand::RevertAll { .. } => {
             migrator.revert_all().await?;
         }
+        PromadSubcommand::Reset { yes, no_revert, .. } => {
+            if !yes && !confirm_reset(migrator.repo.table_name(), !no_revert) {
+                return Err(error::Error::ResetNotConfirmed);
+            }
+            migrator.reset(!no_revert).await?;
+        }
+        #[cfg(feature = "serde")]
+        PromadSubcommand::Export { path } => {
+            let rows = migrator.export_history().await?;
+            let json = serde_json::to_string_pretty(&rows).map_err(|e| history_file(&path, e))?;
+            std::fs::write(&path, json).map_err(|e| history_file(&path, e))?;
+            eprintln!(
+                "{} Exported {} migrations to {}",
+                symbols.ok.bold().green(),
+                rows.len(),
+                path.display()
+            );
+        }
+        #[cfg(feature = "serde")]
+        PromadSubcommand::Import { path, force } => {
+            let json = std::fs::read_to_string(&path).map_err(|e| history_file(&path, e))?;
+            let rows: Vec<crate::repo::PromadRow> =
+                serde_json::from_str(&json).map_err(|e| history_file(&path, e))?;
+            migrator.import_history(&rows, force).await?;
+            eprintln!(
+                "{} Imported {} migrations from {}",
+                symbols.ok.bold().green(),
+                rows.len(),
+                path.display()
+            );
+        }
     }
     Ok(())
 }
+
+/// An error reading or writing the history file at `path`.
+#[cfg(feature = "serde")]
+fn history_file(path: &Path, e: impl std::fmt::Display) -> error::Error {
+    error::Error::HistoryFile {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    }
+}
@@ -0,0 +1,88 @@
+// ┌───────────────────────────────────────────────────────────────────────────┐
+// │                                                                           │
+// │  ██████╗ ██████╗  ██████╗   Copyright (C) The Prospective Company         │
+// │  ██╔══██╗██╔══██╗██╔═══██╗  All Rights Reserved - April 2022              │
+// │  ██████╔╝██████╔╝██║   ██║                                                │
+// │  ██╔═══╝ ██╔══██╗██║   ██║  Proprietary and confidential. Unauthorized    │
+// │  ██║     ██║  ██║╚██████╔╝  copying of this file, via any medium is       │
+// │  ╚═╝     ╚═╝  ╚═╝ ╚═════╝   strictly prohibited.                          │
+// │                                                                           │
+// └───────────────────────────────────────────────────────────────────────────┘
+
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use sqlx::Database;
+
+use crate::{Migration, MigrationCtx};
+
+/// The closure type `FnMigration` runs in each direction. It's handed the
+/// read connection and then the write connection.
+type MigrationFn<DB> = Box<
+    dyn for<'a> Fn(
+            &'a mut <DB as Database>::Connection,
+            &'a mut <DB as Database>::Connection,
+        ) -> BoxFuture<'a, crate::error::Result<()>>
+        + Send
+        + Sync,
+>;
+
+/// A migration made from a name and a pair of async closures, for one-off
+/// programmatic migrations that don't warrant a struct of their own.
+/// Each closure returns a boxed future, so it usually ends in
+/// `async move { ... }.boxed()`. Since the closures are handed both
+/// connections, the migration can't run on a migrator built with
+/// `Migrator::from_connection`.
+pub struct FnMigration<DB: Database> {
+    name: Cow<'static, str>,
+    up: MigrationFn<DB>,
+    down: MigrationFn<DB>,
+}
+
+impl<DB: Database> FnMigration<DB> {
+    pub fn new<U, D>(name: impl Into<Cow<'static, str>>, up: U, down: D) -> Self
+    where
+        U: for<'a> Fn(
+                &'a mut <DB as Database>::Connection,
+                &'a mut <DB as Database>::Connection,
+            ) -> BoxFuture<'a, crate::error::Result<()>>
+            + Send
+            + Sync
+            + 'static,
+        D: for<'a> Fn(
+                &'a mut <DB as Database>::Connection,
+                &'a mut <DB as Database>::Connection,
+            ) -> BoxFuture<'a, crate::error::Result<()>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            name: name.into(),
+            up: Box::new(up),
+            down: Box::new(down),
+        }
+    }
+}
+
+#[async_trait]
+impl<DB: Database> Migration<DB> for FnMigration<DB> {
+    fn name(&self) -> Cow<'static, str> {
+        self.name.clone()
+    }
+
+    async fn up(&self, ctx: &mut MigrationCtx<'_, DB>) -> crate::error::Result<()> {
+        let (read, write) = ctx.connections()?;
+        (self.up)(read, write).await
+    }
+
+    async fn down(&self, ctx: &mut MigrationCtx<'_, DB>) -> crate::error::Result<()> {
+        let (read, write) = ctx.connections()?;
+        (self.down)(read, write).await
+    }
+
+    fn needs_read_connection(&self) -> bool {
+        true
+    }
+}
@@ -11,12 +11,24 @@
 
 use std::sync::{PoisonError, RwLockReadGuard, RwLockWriteGuard};
 
+use crate::{Direction, PreflightCheck};
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Sqlx error: {0}")]
     DatabaseError(#[from] sqlx::Error),
+    #[error("Migration {name} failed while running {direction:?}: {source}")]
+    MigrationFailed {
+        name: String,
+        direction: Direction,
+        source: sqlx::Error,
+    },
     #[error("No such migration: {0}")]
     NoSuchMigration(String),
+    #[error("Migration {0} builds its SQL at runtime, so it can't be shown")]
+    NotRenderable(String),
+    #[error("No migration with ordering key: {0}")]
+    NoSuchOrderingKey(i64),
     #[error("{db_migration_count} migrations have been applied to the database, but {local_migration_count} migrations have been found locally")]
     DeletedMigrations {
         db_migration_count: usize,
@@ -24,6 +36,12 @@ pub enum Error {
     },
     #[error("Duplicate migration name: {0}")]
     DuplicateMigrationName(String),
+    #[error("Migration name {name:?} is longer than the {max} characters the database allows")]
+    MigrationNameTooLong { name: String, max: usize },
+    #[error("Invalid migration name {name:?}: {reason}")]
+    InvalidMigrationName { name: String, reason: String },
+    #[error("Invalid migration file {path}: {reason}")]
+    InvalidMigrationFile { path: String, reason: String },
     #[error(
         "The migration history shows that {remote_name} should be the next migration, but locally there is {local_name}"
     )]
@@ -31,8 +49,113 @@ pub enum Error {
         remote_name: String,
         local_name: String,
     },
+    #[error("Migration {name} depends on {dependency}, which doesn't exist")]
+    MissingDependency { name: String, dependency: String },
+    #[error("Migration {0} depends on itself through its dependencies")]
+    CyclicDependency(String),
+    #[error("Migration {name} depends on {dependency}, which has a later timestamp")]
+    DependencyOutOfOrder { name: String, dependency: String },
+    #[error("Migrations {first} and {second} both have ordering key {key}")]
+    DuplicateOrderingKey {
+        key: i64,
+        first: String,
+        second: String,
+    },
+    #[error("Migration {name} has ordering key {key}, which isn't after the migration before it")]
+    OrderingKeyOutOfOrder { name: String, key: i64 },
+    #[error("Migration {name} has ordering key {key} instead of {expected}, leaving a gap")]
+    OrderingKeyGap {
+        name: String,
+        key: i64,
+        expected: i64,
+    },
+    #[error("Reverting migration {0} changed nothing, so its down is probably missing. Mark it irreversible if that's intended")]
+    EmptyRevert(String),
+    #[error("Migration {0} can't run in a transaction")]
+    NonTransactionalMigration(String),
+    #[error("Migration {0} needs a separate read connection, which a migrator built from a single connection doesn't have")]
+    ReadConnectionRequired(String),
+    #[error("This needs a connection pool, but the migrator was built from a single connection")]
+    PoolRequired,
+    #[error("Migration {0} saves state, which needs a connection pool, but the migrator was built from a single connection")]
+    StateRequiresPool(String),
+    #[error("Migration {name} can't be applied before {skipped}, which isn't in the group")]
+    GroupSkipsMigration { name: String, skipped: String },
+    #[error("The migrations table already has {0} rows, import with force to replace them")]
+    HistoryNotEmpty(usize),
+    #[error("Can't use history file {path}: {reason}")]
+    HistoryFile { path: String, reason: String },
+    #[error("Migration {0} has already been applied")]
+    AlreadyApplied(String),
+    #[error("Migration {name} can't be faked before {pending}, which hasn't been applied")]
+    FakeOutOfOrder { name: String, pending: String },
+    #[error("Migration {0} is irreversible and can't be reverted without forcing it")]
+    IrreversibleMigration(String),
+    #[error("Migration {name} has changed since it was applied: checksum {applied} doesn't match {local}")]
+    ChecksumMismatch {
+        name: String,
+        applied: String,
+        local: String,
+    },
     #[error("Failed to acquire cache log")]
     LockError(String),
+    #[error("Read only transactions aren't supported on {0}")]
+    ReadOnlyUnsupported(String),
+    #[error("Setting the transaction isolation level isn't supported on {0}")]
+    IsolationUnsupported(String),
+    #[error("Switching roles isn't supported on {0}")]
+    RoleUnsupported(String),
+    #[error("Session setup and teardown statements aren't supported on {0}")]
+    SessionHooksUnsupported(String),
+    #[error("Invalid role name {0:?}, expected a plain identifier")]
+    InvalidRoleName(String),
+    #[error("Invalid configuration: {0}")]
+    ConfigError(String),
+    #[error(
+        "Timed out after {timeout:?} waiting for a {phase} connection. Each migration holds a read and a write connection at once, so the pool may be too small"
+    )]
+    AcquireTimeout {
+        phase: &'static str,
+        timeout: std::time::Duration,
+    },
+    #[error(
+        "The pool allows {configured} connections but migrating needs {needed}: a read and a write connection per migration running at once, plus one for each lock watchdog and one for the lock"
+    )]
+    PoolTooSmall { needed: usize, configured: usize },
+    #[error("Dropping the migrations table isn't supported on {0}")]
+    DropUnsupported(String),
+    #[error(
+        "The migrations table can't roll back what's recorded in it, so dry runs aren't supported"
+    )]
+    DryRunUnsupported,
+    #[error("Reset wasn't confirmed, so nothing was changed")]
+    ResetNotConfirmed,
+    #[error("Saving migration state isn't supported on {0}")]
+    StateUnsupported(String),
+    #[error("Snapshotting the schema isn't supported on {0}")]
+    SchemaSnapshotUnsupported(String),
+    #[error("The migrations table has format version {found}, newer than the {supported} this version of promad supports")]
+    UnsupportedTableFormat { found: i32, supported: i32 },
+    #[error("Unsupported database backend {0}, only PostgreSQL, MySQL and SQLite are supported")]
+    UnsupportedBackend(String),
+    #[error("Locking with {0} isn't supported")]
+    LockUnsupported(String),
+    #[error(
+        "Unsupported database URL scheme {0:?}, this build of promad can't migrate that database"
+    )]
+    UnsupportedDatabaseScheme(String),
+    #[error("Stdout is already redirected by another interactive UI")]
+    StdoutRedirectHeld,
+    #[error("Interrupted after {} migrations completed, the migration in progress was rolled back", completed.len())]
+    Interrupted { completed: Vec<String> },
+    #[error("The migration lock is held by another migrator")]
+    LockUnavailable,
+    #[error("Preflight check failed: {check}: {source}")]
+    PreflightFailed {
+        check: PreflightCheck,
+        #[source]
+        source: Box<Error>,
+    },
 }
 
 impl<'a, T> From<PoisonError<RwLockReadGuard<'a, T>>> for Error {
@@ -47,4 +170,141 @@ impl<'a, T> From<PoisonError<RwLockWriteGuard<'a, T>>> for Error {
     }
 }
 
+impl Error {
+    /// Whether the error is a transient connection failure (connection closed,
+    /// broken pipe, failover) that is safe to retry. SQL errors such as syntax
+    /// or constraint violations are never transient.
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            Error::DatabaseError(e) => is_transient_sqlx_error(e),
+            Error::AcquireTimeout { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the error is what Postgres raises when another session
+    /// creates the same table, type or index at the same moment, despite
+    /// `IF NOT EXISTS`, or when two sessions doing so deadlock.
+    pub(crate) fn is_concurrent_ddl(&self) -> bool {
+        self.as_database_error()
+            .and_then(|db| db.code())
+            .is_some_and(|code| matches!(&*code, "23505" | "42P07" | "42710" | "40P01"))
+    }
+
+    /// The sqlx error behind this one, if any, including the one a failed
+    /// migration or preflight check wraps.
+    pub fn as_sqlx_error(&self) -> Option<&sqlx::Error> {
+        match self {
+            Error::DatabaseError(e) | Error::MigrationFailed { source: e, .. } => Some(e),
+            Error::PreflightFailed { source, .. } => source.as_sqlx_error(),
+            _ => None,
+        }
+    }
+
+    /// The error the database returned, if any, for inspecting its code or
+    /// constraint.
+    pub fn as_database_error(&self) -> Option<&dyn sqlx::error::DatabaseError> {
+        match self.as_sqlx_error()? {
+            sqlx::Error::Database(db) => Some(&**db),
+            _ => None,
+        }
+    }
+
+    /// Whether the database rejected a row for violating a unique
+    /// constraint, such as a migration that's already been recorded.
+    pub fn is_unique_violation(&self) -> bool {
+        self.as_database_error()
+            .is_some_and(|db| db.is_unique_violation())
+    }
+
+    /// Whether the connection to the database failed, as opposed to the
+    /// database rejecting a statement.
+    pub fn is_connection_error(&self) -> bool {
+        match self.as_sqlx_error() {
+            Some(
+                sqlx::Error::Io(_)
+                | sqlx::Error::Tls(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed,
+            ) => true,
+            Some(sqlx::Error::Database(db)) => db.code().is_some_and(|code| code.starts_with("08")),
+            _ => false,
+        }
+    }
+
+    /// The process exit code for this error, so scripts can tell failures
+    /// apart:
+    ///
+    /// | Code | Errors |
+    /// |------|--------|
+    /// | 1    | anything not listed below |
+    /// | 2    | the applied history doesn't match the local migrations |
+    /// | 3    | no migration with the given name or ordering key |
+    /// | 4    | the database returned an error |
+    /// | 5    | the migration lock or a connection couldn't be acquired |
+    /// | 130  | interrupted by Ctrl-C |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::HistoryMigrationMismatch { .. }
+            | Error::DeletedMigrations { .. }
+            | Error::ChecksumMismatch { .. } => 2,
+            Error::NoSuchMigration(_) | Error::NoSuchOrderingKey(_) => 3,
+            Error::DatabaseError(_) | Error::MigrationFailed { .. } => 4,
+            Error::LockUnavailable | Error::AcquireTimeout { .. } => 5,
+            Error::Interrupted { .. } => 130,
+            _ => 1,
+        }
+    }
+
+    /// Attach the migration that was running to a database error.
+    pub(crate) fn in_migration(self, name: &str, direction: Direction) -> Self {
+        match self {
+            Error::DatabaseError(source) => Error::MigrationFailed {
+                name: name.to_string(),
+                direction,
+                source,
+            },
+            e => e,
+        }
+    }
+}
+
+/// Classify a sqlx error as a transient connection failure.
+fn is_transient_sqlx_error(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        sqlx::Error::Database(db) => db.code().is_some_and(|code| {
+            // Class 08 is "connection exception", 57P01-57P03 are server
+            // shutdown/crash/cannot-connect-now during a failover.
+            code.starts_with("08") || matches!(&*code, "57P01" | "57P02" | "57P03")
+        }),
+        _ => false,
+    }
+}
+
 pub type Result<A> = std::result::Result<A, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_transient() {
+        let io = || std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(Error::DatabaseError(sqlx::Error::Io(io())).is_transient());
+        assert!(Error::DatabaseError(sqlx::Error::PoolTimedOut).is_transient());
+        assert!(Error::AcquireTimeout {
+            phase: "write",
+            timeout: std::time::Duration::from_secs(1),
+        }
+        .is_transient());
+
+        assert!(!Error::DatabaseError(sqlx::Error::RowNotFound).is_transient());
+        assert!(!Error::PoolRequired.is_transient());
+        // Once it's pinned on a migration it's no longer retried.
+        assert!(!Error::DatabaseError(sqlx::Error::Io(io()))
+            .in_migration("migration1", Direction::Up)
+            .is_transient());
+    }
+}
@@ -9,11 +9,22 @@
 // │                                                                           │
 // └───────────────────────────────────────────────────────────────────────────┘
 
-#![doc = include_str!("../README.md")]
+// The README's example runs against Postgres.
+#![cfg_attr(feature = "postgres", doc = include_str!("../README.md"))]
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar};
 use repo::CachedPromadRepo;
-use std::{collections::HashSet, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet},
+    future::Future,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 use once_cell::sync::Lazy;
 
@@ -26,13 +37,25 @@ use repo::postgres::PostgresPromadRepo;
 use sqlx::Postgres;
 
 use colored::Colorize;
-use sqlx::{Connection, Database, Pool};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use sqlx::{pool::PoolConnection, ConnectOptions, Connection, Database, Pool, Transaction};
 use std::io::Write;
 
+pub mod checksum;
 pub mod cli;
+pub mod closure;
 pub mod error;
 pub mod repo;
+pub mod sql;
+mod term;
 
+pub use closure::FnMigration;
+pub use indicatif::ProgressStyle;
+#[cfg(feature = "include_dir")]
+pub use sql::EmbeddedSource;
+pub use sql::{exec_batch, FsSource, MigrationSource, SqlFileMigration};
+
+use crate::checksum::Checksum;
 use crate::repo::{PromadRepo, PromadRow};
 
 /// Good default for migration names.
@@ -59,49 +82,572 @@ macro_rules! file_basename {
 /// everything occurs in the same txn.
 #[async_trait]
 pub trait Migration<DB: Database>: Send + Sync {
-    fn name(&self) -> &'static str;
+    fn name(&self) -> Cow<'static, str>;
     /// Runs the migration. Note that any stdout will be captured until the migration is complete.
     /// Then all of the captured stdout text is printed to the console.
-    async fn up(
-        &self,
-        read: &mut <DB as Database>::Connection,
-        write: &mut <DB as Database>::Connection,
-    ) -> crate::error::Result<()>;
+    async fn up(&self, ctx: &mut MigrationCtx<'_, DB>) -> crate::error::Result<()>;
     /// Reverts the migration. Note that any stdout will be captured until the migration is complete.
     /// Then all of the captured stdout text is printed to the console.
-    async fn down(
-        &self,
-        read: &mut <DB as Database>::Connection,
-        write: &mut <DB as Database>::Connection,
-    ) -> crate::error::Result<()>;
+    async fn down(&self, ctx: &mut MigrationCtx<'_, DB>) -> crate::error::Result<()>;
+    /// Whether the migration can be reverted. Destructive migrations should return
+    /// false so that reverting them is an error rather than a silent no-op `down`.
+    fn reversible(&self) -> bool {
+        true
+    }
+    /// Checksum of the migration's contents. When given it's stored with the
+    /// migration so that editing an applied migration is caught by validation.
+    fn checksum(&self) -> Option<Checksum> {
+        None
+    }
+    /// The SQL the migration runs, for migrations whose SQL is fixed.
+    /// Migrations that build their SQL at runtime return `None`.
+    fn render(&self) -> Option<RenderedMigration> {
+        None
+    }
+    /// Names of migrations that have to be applied before this one,
+    /// wherever they are in the migrator.
+    fn depends_on(&self) -> &[&'static str] {
+        &[]
+    }
+    /// Whether a parallel run may start the migration without waiting for
+    /// the one before it, although it has no `depends_on`. Only for
+    /// migrations that touch nothing an earlier migration does.
+    fn independent(&self) -> bool {
+        false
+    }
+    /// Whether the migration can run inside a transaction. Migrations that
+    /// can't, such as ones running `CREATE INDEX CONCURRENTLY`, return
+    /// `false` so they're never handed a caller's transaction by
+    /// `Migrator::apply_all_in`.
+    fn transactional(&self) -> bool {
+        true
+    }
+    /// Whether the migration needs a read connection separate from the
+    /// write connection, to use `MigrationCtx::connections`. Such
+    /// migrations can't run on a migrator built with
+    /// `Migrator::from_connection`.
+    fn needs_read_connection(&self) -> bool {
+        false
+    }
+    /// A fixed ordering key for the migration, stored when it's applied.
+    /// Declaring keys with gaps between them (say 1000, 2000) leaves room
+    /// to insert migrations between them later. Defaults to `None`, which
+    /// keys the migration by the ordering strategy.
+    fn ordering_key(&self) -> Option<i64> {
+        None
+    }
+    /// Tags for `Migrator::with_tag_filter`, such as the services that run
+    /// the migration. Untagged migrations are run by every migrator.
+    fn tags(&self) -> &[&'static str] {
+        &[]
+    }
+    /// A sentence on what the migration does, shown next to its name by the
+    /// CLI's `list`. Never stored in the migrations table.
+    fn description(&self) -> Option<&str> {
+        None
+    }
+    /// Statements run on the write connection, inside the migration's
+    /// transaction, before `up`. For session tuning such as
+    /// `SET LOCAL work_mem = '1GB'` that shouldn't clutter `up` itself.
+    fn session_setup(&self) -> &[&str] {
+        &[]
+    }
+    /// Statements run on the write connection after `up` to undo what
+    /// `session_setup` changed. If `up` fails in a transaction they're
+    /// skipped, since the transaction is aborted and rolling it back undoes
+    /// the setup as well; a non-transactional migration still runs them.
+    fn session_teardown(&self) -> &[&str] {
+        &[]
+    }
+    /// When the migration was written, used as its ordering key with
+    /// `OrderingStrategy::Timestamp`. Defaults to the digits the name starts
+    /// with, so `20230522093000_create_users` gives `20230522093000`.
+    fn timestamp(&self) -> Option<i64> {
+        let name = self.name();
+        let digits = name
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(&*name, |end| &name[..end]);
+        digits.parse().ok()
+    }
+}
+
+/// Everything a migration gets access to while it runs.
+pub struct MigrationCtx<'a, DB: Database> {
+    read: Option<&'a mut <DB as Database>::Connection>,
+    write: &'a mut <DB as Database>::Connection,
+    direction: Direction,
+    dry_run: bool,
+    progress: &'a dyn ProgressReporter,
+    table_name: &'a str,
+    migrator: &'a Migrator<DB>,
+    migration: Cow<'static, str>,
+}
+
+impl<'a, DB: Database> MigrationCtx<'a, DB> {
+    /// The read only connection, in its own transaction. On a migrator
+    /// built with `Migrator::from_connection` this is the write connection.
+    pub fn read(&mut self) -> &mut <DB as Database>::Connection {
+        match self.read {
+            Some(ref mut read) => read,
+            None => self.write,
+        }
+    }
+
+    /// The write connection. The migration is recorded in the same transaction.
+    pub fn write(&mut self) -> &mut <DB as Database>::Connection {
+        self.write
+    }
+
+    /// Both connections at once, for streaming from the read connection
+    /// into the write connection. Migrations that use this have to return
+    /// true from `Migration::needs_read_connection`. Fails with
+    /// `Error::ReadConnectionRequired` on a migrator built with
+    /// `Migrator::from_connection`, which only has the write connection.
+    pub fn connections(
+        &mut self,
+    ) -> crate::error::Result<(
+        &mut <DB as Database>::Connection,
+        &mut <DB as Database>::Connection,
+    )> {
+        let read = self
+            .read
+            .as_deref_mut()
+            .ok_or_else(|| error::Error::ReadConnectionRequired(self.migration.to_string()))?;
+        Ok((read, self.write))
+    }
+
+    /// Whether the migration is being applied or reverted.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Whether the write transaction will be rolled back rather than committed.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Used by long running migrations to report how far along they are.
+    pub fn progress(&self) -> &dyn ProgressReporter {
+        self.progress
+    }
+
+    /// Name of the table migrations are tracked in.
+    pub fn table_name(&self) -> &str {
+        self.table_name
+    }
+
+    /// The value this migration saved under `key` with `state_set`,
+    /// possibly during an earlier attempt that failed. Fails with
+    /// `Error::StateRequiresPool` on migrators created with
+    /// `from_connection`.
+    pub async fn state_get(&self, key: &str) -> crate::error::Result<Option<String>> {
+        self.migrator.state_get(&self.migration, key).await
+    }
+
+    /// Save `value` under `key`, for resuming a long migration where a
+    /// failed attempt left off. It's committed straight away on a
+    /// connection of its own, so it survives the migration's transaction
+    /// rolling back, even on a dry run. Everything the migration saved is
+    /// forgotten once it completes. Like `state_get` it needs a pool.
+    pub async fn state_set(&self, key: &str, value: &str) -> crate::error::Result<()> {
+        self.migrator.state_set(&self.migration, key, value).await
+    }
+}
+
+/// Where the migrator gets the time applied migrations are recorded at.
+/// Closures returning a time are clocks too, so tests can pin it.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> chrono::DateTime<Utc>;
+}
+
+/// The system clock. The default.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl<F: Fn() -> chrono::DateTime<Utc> + Send + Sync> Clock for F {
+    fn now(&self) -> chrono::DateTime<Utc> {
+        self()
+    }
 }
 
+/// Creates the UI for a run given the migrations about to be run, along with their ordering keys.
+pub type UiFactory<DB> =
+    Box<dyn Fn(&[(i64, &dyn Migration<DB>)]) -> Box<dyn MigrationUI> + Send + Sync>;
+
+/// A migrator's pool and read pool.
+type Pools<DB> = (Option<Pool<DB>>, Option<Pool<DB>>);
+
 /// Contains the migrations and logic for managing the migrations table,
 /// handling txn, and ensuring integrity of the migrations.
 pub struct Migrator<DB: Database> {
     pub(crate) migrations: Vec<Box<dyn Migration<DB>>>,
-    pub(crate) pool: Pool<DB>,
+    pub(crate) pool: Option<Pool<DB>>,
+    pub(crate) read_pool: Option<Pool<DB>>,
+    /// The caller's pools while `pool` and `read_pool` are the statement
+    /// logging copies made by `with_sql_logging`.
+    pub(crate) unlogged_pools: Option<Pools<DB>>,
+    pub(crate) connection: Option<futures_util::lock::Mutex<<DB as Database>::Connection>>,
     pub(crate) repo: Box<dyn PromadRepo<DB>>,
-    pub(crate) ui_factory: Box<dyn Fn(&[(i64, &dyn Migration<DB>)]) -> Box<dyn MigrationUI>>,
+    pub(crate) ui_factory: UiFactory<DB>,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) force_irreversible: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) lock_strategy: LockStrategy,
+    pub(crate) ordering_strategy: OrderingStrategy,
+    pub(crate) case_insensitive_names: bool,
+    pub(crate) lock_watchdog: Option<Duration>,
+    pub(crate) actor: Option<String>,
+    pub(crate) isolation: Option<IsolationLevel>,
+    pub(crate) role: Option<String>,
+    pub(crate) skip_validation: bool,
+    pub(crate) parallelism: usize,
+    pub(crate) acquire_timeout: Option<Duration>,
+    pub(crate) clock: Box<dyn Clock>,
+    pub(crate) timestamp_precision: TimestampPrecision,
+    pub(crate) strict_revert: bool,
+    pub(crate) no_op_down: Duration,
+    pub(crate) tag_filter: Option<String>,
+    pub(crate) before_all: Vec<String>,
+    pub(crate) after_all: Vec<String>,
+    pub(crate) notify_channel: Option<String>,
+    pub(crate) skip_missing_on_revert: bool,
+    pub(crate) numbered_names: Option<usize>,
+    pub(crate) contiguous_ordering_keys: bool,
+    /// Set once clock skew in the applied history has been warned about.
+    pub(crate) clock_skew_warned: AtomicBool,
+}
+
+/// How many times to attempt a migration when the connection fails
+/// transiently, e.g. during a database failover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry. Doubled after every failed attempt.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before retrying after failed attempt number
+    /// `attempt`, counting from 1, or `None` if that was the last one.
+    /// The delay saturates rather than overflowing.
+    pub(crate) fn delay_after(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        Some(self.backoff.saturating_mul(factor))
+    }
+}
+
+/// How concurrent migrators are kept from running at the same time.
+///
+/// Only the transaction scoped strategies work through PgBouncer in
+/// transaction pooling mode, since session state such as a session level
+/// advisory lock doesn't survive between transactions there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockStrategy {
+    /// Session level advisory lock (`pg_advisory_lock`) on a dedicated connection
+    /// for the duration of the operation. The default. Not PgBouncer compatible.
+    Advisory(i64),
+    /// Transaction level advisory lock (`pg_advisory_xact_lock`) held in a transaction
+    /// that's kept open for the duration of the operation. PgBouncer compatible.
+    AdvisoryXact(i64),
+    /// Lock the tracking table itself in a transaction that's kept open for the
+    /// duration of the operation. Doesn't block recording migrations. PgBouncer compatible.
+    TableLock,
+    /// Don't lock at all. Only safe if a single migrator runs at a time.
+    None,
+}
+
+/// Isolation level of the transactions migrations run in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    /// Every statement sees the same snapshot, so the read connection
+    /// gives a consistent view while the migration streams from it.
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    /// The level as written in `SET TRANSACTION ISOLATION LEVEL`.
+    pub(crate) fn as_sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// How the ordering key stored with each applied migration is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrderingStrategy {
+    /// The migration's position in the migrator. The default. Inserting a
+    /// migration before others changes their keys.
+    #[default]
+    Sequential,
+    /// The migration's `timestamp()`, which stays the same wherever it's
+    /// added. Migrations without one fall back to their position.
+    Timestamp,
+}
+
+/// How precisely the time a migration was applied is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampPrecision {
+    Seconds,
+    Millis,
+    /// What Postgres stores. The default.
+    #[default]
+    Micros,
+}
+
+impl TimestampPrecision {
+    /// `time` with anything finer than this precision cut off.
+    pub fn truncate(self, time: chrono::DateTime<Utc>) -> chrono::DateTime<Utc> {
+        use chrono::SubsecRound;
+
+        let digits = match self {
+            TimestampPrecision::Seconds => 0,
+            TimestampPrecision::Millis => 3,
+            TimestampPrecision::Micros => 6,
+        };
+        time.trunc_subsecs(digits)
+    }
+}
+
+/// How many times to try creating the tables when other processes are
+/// creating them at the same moment.
+const INIT_ATTEMPTS: u32 = 3;
+
+/// A `down` that returns faster than this can't have made a round trip to
+/// the database, so it most likely does nothing. The default for
+/// `Migrator::with_no_op_down_threshold`.
+pub const NO_OP_DOWN: Duration = Duration::from_micros(10);
+
+/// Advisory lock key used unless another is configured.
+pub const DEFAULT_LOCK_KEY: i64 = 0x70726f6d6164;
+
+impl Default for LockStrategy {
+    fn default() -> Self {
+        LockStrategy::Advisory(DEFAULT_LOCK_KEY)
+    }
+}
+
+/// Holds onto the connection the lock was taken on.
+enum LockGuard<DB: Database> {
+    None,
+    Session(SessionLock<DB>),
+    Transaction(Transaction<'static, DB>),
+}
+
+/// The connection holding a session lock. If the operation is cancelled
+/// before the lock is released, the connection is closed rather than
+/// returned to the pool, since closing the session is what releases the
+/// lock.
+struct SessionLock<DB: Database>(Option<PoolConnection<DB>>);
+
+impl<DB: Database> SessionLock<DB> {
+    fn into_inner(mut self) -> PoolConnection<DB> {
+        self.0.take().expect("session lock connection taken twice")
+    }
+}
+
+impl<DB: Database> Drop for SessionLock<DB> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.0.take() {
+            drop(conn.detach());
+        }
+    }
 }
 
 /// Used for representing the status of a migration to the CLI frontend.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UiMigration {
-    name: &'static str,
+    name: Cow<'static, str>,
+    ordering_key: i64,
     run_at: Option<chrono::DateTime<Utc>>,
+    applied_by: Option<String>,
+    description: Option<String>,
+}
+
+impl UiMigration {
+    /// The migration's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// When the migration was applied, or `None` if it is pending.
+    pub fn run_at(&self) -> Option<chrono::DateTime<Utc>> {
+        self.run_at
+    }
+
+    /// Who applied the migration, if it has been applied and that was recorded.
+    pub fn applied_by(&self) -> Option<&str> {
+        self.applied_by.as_deref()
+    }
+
+    /// The migration's `description`, if it has one.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+}
+
+/// The SQL a migration runs in each direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedMigration {
+    pub up_sql: String,
+    /// `None` when the migration can't be reverted.
+    pub down_sql: Option<String>,
+}
+
+/// A migration that `apply_all` would run, in the order it would run it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMigration {
+    pub name: Cow<'static, str>,
+    /// The ordering key it will be recorded with.
+    pub ordering_key: i64,
+}
+
+/// A way the applied history has drifted from the local migrations,
+/// reported by `Migrator::drift`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// An applied migration that's gone locally, but whose checksum matches
+    /// a local migration that isn't applied, so it was probably renamed.
+    Renamed { applied: String, local: String },
+    /// A migration that was applied in a different order, relative to the
+    /// other applied migrations, than it has locally. Only the fewest
+    /// migrations whose moving explains the difference are reported, so
+    /// one migration out of place doesn't flag those it was moved past.
+    /// Positions are indexes into the applied history and the local
+    /// migrations.
+    Reordered {
+        name: String,
+        applied_position: usize,
+        local_position: usize,
+    },
+    /// A migration whose checksum has changed since it was applied.
+    Edited {
+        name: String,
+        applied: String,
+        local: String,
+    },
+    /// An applied migration with no local migration to match it.
+    Orphaned { name: String },
+}
+
+/// What `run_pending` applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplyReport {
+    /// Names of the migrations that were applied, in the order they ran.
+    pub applied: Vec<Cow<'static, str>>,
+    /// How long the whole run took, including validation.
+    pub elapsed: Duration,
+}
+
+/// What `Migrator::revert_all` and `Migrator::revert_to_inclusive` reverted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevertReport {
+    /// Names of the migrations that were reverted, in the order they ran,
+    /// so the most recently applied comes first.
+    pub reverted: Vec<Cow<'static, str>>,
+    /// How long the whole run took, including validation.
+    pub elapsed: Duration,
+}
+
+/// How a migration fared in `Migrator::test_reversibility`.
+#[cfg(feature = "testing")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReversibilityResult {
+    pub name: Cow<'static, str>,
+    /// Irreversible migrations are only applied, so have no differences.
+    pub reversible: bool,
+    /// Schema lines that `down` didn't restore or that re-applying changed,
+    /// prefixed with `+` if they were added and `-` if they went missing.
+    pub differences: Vec<String>,
+}
+
+#[cfg(feature = "testing")]
+impl ReversibilityResult {
+    /// Whether `down` exactly undid `up`.
+    pub fn is_symmetric(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// A migration that has been applied, as returned by `Migrator::applied`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub name: Cow<'static, str>,
+    /// The ordering key it was recorded with.
+    pub ordering_key: i64,
+    pub applied_at: chrono::DateTime<Utc>,
+    /// The local migration's `description`.
+    pub description: Option<String>,
 }
 
 static DEFAULT_PROGRESS_STYLE: Lazy<ProgressStyle> = Lazy::new(|| {
     ProgressStyle::default_spinner()
-        .tick_chars("◐◓◑◒ ")
+        .tick_chars(term::symbols().ticks)
         .template("{spinner:.dim.bold} {prefix:.bold.dim} {msg}")
         .unwrap()
 });
 
+static BAR_PROGRESS_STYLE: Lazy<ProgressStyle> = Lazy::new(|| {
+    ProgressStyle::default_bar()
+        .tick_chars(term::symbols().ticks)
+        .template("{spinner:.dim.bold} {prefix:.bold.dim} {bar:30} {pos}/{len} {msg}")
+        .unwrap()
+});
+
+/// Lets a migration report its progress, e.g. the number of rows copied
+/// while streaming data from the read connection to the write connection.
+pub trait ProgressReporter: Send + Sync {
+    /// Set the total amount of work the migration is going to do.
+    fn set_length(&self, len: u64);
+    /// Advance the progress by `delta`.
+    fn inc(&self, delta: u64);
+}
+
+/// Progress reporter that ignores everything it's told.
+pub struct NoopProgressReporter;
+
+impl ProgressReporter for NoopProgressReporter {
+    fn set_length(&self, _len: u64) {}
+
+    fn inc(&self, _delta: u64) {}
+}
+
+/// What happened during a run, handed to the UI once it completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Number of migrations that were run.
+    pub count: usize,
+    /// Whether the migrations were applied or reverted.
+    pub direction: Direction,
+    /// How long running all of the migrations took.
+    pub elapsed: Duration,
+}
+
 /// Manage the UI for migrations. This is used to show progress bars
 /// and other information to the user.
-pub trait MigrationUI {
+pub trait MigrationUI: Send + Sync {
     /// Start a migration. This is called before the migration is run.
     /// The index is used to lookup which migration is running.
     fn start(&self, idx: usize, direction: &Direction);
@@ -109,25 +655,115 @@ pub trait MigrationUI {
     fn finish(&self, idx: usize);
     /// Called at the end if any migrations ran. Just used to indicate
     /// to the user that their actions all completed successfully.
-    fn complete(&self);
+    fn complete(&self, summary: &RunSummary);
+    /// Called instead of `finish` when the migration at `idx` fails.
+    fn fail(&self, _idx: usize, _err: &crate::error::Error) {}
+    /// Returns the reporter handed to the migration at `idx` while it runs.
+    fn progress(&self, _idx: usize) -> Box<dyn ProgressReporter> {
+        Box::new(NoopProgressReporter)
+    }
 }
 
+/// Set while an `InteractiveMigrationUI` holds the stdout redirect, since
+/// only one redirect can exist per process.
+static STDOUT_REDIRECT_HELD: AtomicBool = AtomicBool::new(false);
+
 /// Interactive UI that uses indicatif to show pretty progress bars.
 /// This also redirects stdout to a buffer so that the progress bars
 /// aren't broken by stdout output. It's later printed to the screen
 /// when migrations are complete.
 ///
-/// This means that it's not thread safe as multiple instances will
-/// all try to redirect stdout and step on each other.
+/// Only one instance can redirect stdout at a time. While one is alive,
+/// `new` falls back to a `PlainMigrationUI` and `try_new` returns an error.
 pub struct InteractiveMigrationUI {
     _multi_progress: MultiProgress,
-    _redirector: gag::Hold,
+    redirector: Mutex<Option<gag::Hold>>,
     progress_bars: Vec<ProgressBar>,
+    bar_style: ProgressStyle,
+    tick_interval: Duration,
+}
+
+/// How an `InteractiveMigrationUI` draws its spinners. The defaults are
+/// what `Migrator::create` uses.
+#[derive(Clone)]
+pub struct InteractiveUiConfig {
+    /// Style of a migration's spinner.
+    pub style: ProgressStyle,
+    /// Style a migration switches to once it reports how much work it has.
+    pub bar_style: ProgressStyle,
+    /// How often the spinners advance.
+    pub tick_interval: Duration,
+}
+
+impl Default for InteractiveUiConfig {
+    fn default() -> Self {
+        Self {
+            style: (*DEFAULT_PROGRESS_STYLE).clone(),
+            bar_style: (*BAR_PROGRESS_STYLE).clone(),
+            tick_interval: Duration::from_millis(100),
+        }
+    }
 }
 
 impl InteractiveMigrationUI {
-    fn new<DB: Database>(migrations: &[(i64, &dyn Migration<DB>)]) -> Box<dyn MigrationUI> {
-        let redirector = gag::Hold::stdout().unwrap();
+    /// Create the UI, or a `PlainMigrationUI` if another interactive UI is
+    /// already holding stdout, boxed since the two are different types.
+    pub fn boxed<DB: Database>(migrations: &[(i64, &dyn Migration<DB>)]) -> Box<dyn MigrationUI> {
+        Self::boxed_with_config(migrations, &InteractiveUiConfig::default())
+    }
+
+    /// Like `boxed`, drawn according to `config`.
+    pub fn boxed_with_config<DB: Database>(
+        migrations: &[(i64, &dyn Migration<DB>)],
+        config: &InteractiveUiConfig,
+    ) -> Box<dyn MigrationUI> {
+        match Self::try_new(migrations) {
+            Ok(ui) => Box::new(ui.with_config(config)),
+            Err(_) => Box::new(PlainMigrationUI::new(migrations)),
+        }
+    }
+
+    /// Draw the spinners with `style` instead of the default.
+    pub fn with_style(self, style: ProgressStyle) -> Self {
+        for progress in &self.progress_bars {
+            progress.set_style(style.clone());
+        }
+        self
+    }
+
+    /// Draw progress bars with `style` instead of the default, for
+    /// migrations that report how much work they have.
+    pub fn with_bar_style(mut self, style: ProgressStyle) -> Self {
+        self.bar_style = style;
+        self
+    }
+
+    /// Advance the spinners every `interval` instead of every 100ms.
+    pub fn with_tick_interval(mut self, interval: Duration) -> Self {
+        self.tick_interval = interval;
+        self
+    }
+
+    /// Apply everything in `config`.
+    pub fn with_config(self, config: &InteractiveUiConfig) -> Self {
+        self.with_style(config.style.clone())
+            .with_bar_style(config.bar_style.clone())
+            .with_tick_interval(config.tick_interval)
+    }
+
+    /// Create the UI, failing with `StdoutRedirectHeld` if another
+    /// interactive UI is already holding stdout.
+    pub fn try_new<DB: Database>(
+        migrations: &[(i64, &dyn Migration<DB>)],
+    ) -> crate::error::Result<Self> {
+        if STDOUT_REDIRECT_HELD.swap(true, Ordering::SeqCst) {
+            return Err(error::Error::StdoutRedirectHeld);
+        }
+        // Something outside of promad may have redirected stdout with gag.
+        let Ok(redirector) = gag::Hold::stdout() else {
+            STDOUT_REDIRECT_HELD.store(false, Ordering::SeqCst);
+            return Err(error::Error::StdoutRedirectHeld);
+        };
         let multi_progress = MultiProgress::new();
         let migrations_len = migrations.len();
         let progress_bars = migrations
@@ -146,18 +782,51 @@ impl InteractiveMigrationUI {
                 progress
             })
             .collect::<Vec<_>>();
-        Box::new(InteractiveMigrationUI {
+        Ok(InteractiveMigrationUI {
             _multi_progress: multi_progress,
-            _redirector: redirector,
+            redirector: Mutex::new(Some(redirector)),
             progress_bars,
+            bar_style: (*BAR_PROGRESS_STYLE).clone(),
+            tick_interval: Duration::from_millis(100),
         })
     }
+
+    /// Stops holding stdout, printing everything the migrations wrote to it
+    /// while the progress bars were up.
+    fn release_stdout(&self) {
+        // Anything still sitting in Rust's stdout buffer has to reach the
+        // redirected descriptor first, or it'd print after the held output.
+        let _ = std::io::stdout().flush();
+        if let Ok(mut redirector) = self.redirector.lock() {
+            if let Some(hold) = redirector.take() {
+                drop(hold);
+                STDOUT_REDIRECT_HELD.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+impl Drop for InteractiveMigrationUI {
+    fn drop(&mut self) {
+        // The UI lives for the duration of the apply loop, so this runs while
+        // unwinding out of a migration that panicked. Stop the spinners and
+        // make sure whatever the migration printed isn't lost with it.
+        if std::thread::panicking() {
+            for progress in &self.progress_bars {
+                if !progress.is_finished() {
+                    progress
+                        .abandon_with_message(format!("{} panicked", term::symbols().fail.red()));
+                }
+            }
+        }
+        self.release_stdout();
+    }
 }
 
 impl MigrationUI for InteractiveMigrationUI {
     fn start(&self, idx: usize, direction: &Direction) {
         let progress = &self.progress_bars[idx];
-        progress.enable_steady_tick(Duration::from_millis(100));
+        progress.enable_steady_tick(self.tick_interval);
         match direction {
             Direction::Up => {
                 progress.set_message("Running up migration");
@@ -170,15 +839,115 @@ impl MigrationUI for InteractiveMigrationUI {
 
     fn finish(&self, idx: usize) {
         let progress = &self.progress_bars[idx];
-        progress.set_message("✓".green().to_string());
+        progress.set_message(term::symbols().ok.green().to_string());
         progress.finish();
     }
 
-    fn complete(&self) {
+    fn fail(&self, idx: usize, err: &crate::error::Error) {
+        let progress = &self.progress_bars[idx];
+        progress.abandon_with_message(format!("{} {err}", term::symbols().fail.red()));
+        self.release_stdout();
+    }
+
+    fn progress(&self, idx: usize) -> Box<dyn ProgressReporter> {
+        Box::new(BarProgressReporter(
+            self.progress_bars[idx].clone(),
+            self.bar_style.clone(),
+        ))
+    }
+
+    fn complete(&self, summary: &RunSummary) {
         // Required because indicatif doesn't write a newline after
         // everything is done :(
         write!(std::io::stderr(), "\n").unwrap();
-        println!("✨ All migrations completed");
+        let verb = match summary.direction {
+            Direction::Up => "Applied",
+            Direction::Down => "Reverted",
+        };
+        println!(
+            "{}{verb} {} migrations in {:.1}s",
+            term::symbols().done,
+            summary.count,
+            summary.elapsed.as_secs_f64()
+        );
+    }
+}
+
+/// UI that prints a line per migration to stderr without touching stdout.
+/// Used when an interactive UI can't take over the terminal.
+pub struct PlainMigrationUI {
+    prefixes: Vec<String>,
+}
+
+impl PlainMigrationUI {
+    pub fn new<DB: Database>(migrations: &[(i64, &dyn Migration<DB>)]) -> Self {
+        let migrations_len = migrations.len();
+        let prefixes = migrations
+            .iter()
+            .enumerate()
+            .map(|(idx, (_i, migration))| {
+                format!("[{}/{migrations_len}] {}", idx + 1, migration.name())
+            })
+            .collect();
+        Self { prefixes }
+    }
+}
+
+impl MigrationUI for PlainMigrationUI {
+    fn start(&self, idx: usize, direction: &Direction) {
+        let verb = match direction {
+            Direction::Up => "Running up migration",
+            Direction::Down => "Running down migration",
+        };
+        eprintln!("{} {verb}", self.prefixes[idx]);
+    }
+
+    fn finish(&self, idx: usize) {
+        eprintln!("{} {}", self.prefixes[idx], term::symbols().ok);
+    }
+
+    fn fail(&self, idx: usize, err: &crate::error::Error) {
+        eprintln!("{} {} {err}", self.prefixes[idx], term::symbols().fail);
+    }
+
+    fn complete(&self, summary: &RunSummary) {
+        let verb = match summary.direction {
+            Direction::Up => "Applied",
+            Direction::Down => "Reverted",
+        };
+        eprintln!(
+            "{}{verb} {} migrations in {:.1}s",
+            term::symbols().done,
+            summary.count,
+            summary.elapsed.as_secs_f64()
+        );
+    }
+}
+
+/// UI that shows nothing, for services that embed promad and want its
+/// output through `tracing` and metrics alone. Unlike
+/// `InteractiveMigrationUI` it never redirects stdout.
+pub struct NullMigrationUI;
+
+impl MigrationUI for NullMigrationUI {
+    fn start(&self, _idx: usize, _direction: &Direction) {}
+
+    fn finish(&self, _idx: usize) {}
+
+    fn complete(&self, _summary: &RunSummary) {}
+}
+
+/// Turns a migration's spinner into a progress bar once it knows its length.
+struct BarProgressReporter(ProgressBar, ProgressStyle);
+
+impl ProgressReporter for BarProgressReporter {
+    fn set_length(&self, len: u64) {
+        self.0.set_style(self.1.clone());
+        self.0.set_length(len);
+    }
+
+    fn inc(&self, delta: u64) {
+        self.0.inc(delta);
     }
 }
 
@@ -189,113 +958,1170 @@ pub enum Direction {
     Down,
 }
 
-pub trait HasPromadRepo: Database {
-    type Repo: PromadRepo<Self>;
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+        }
+    }
 }
 
-impl HasPromadRepo for Postgres {
-    type Repo = PostgresPromadRepo;
+/// The checks run by `Migrator::preflight`, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightCheck {
+    Connection,
+    TrackingTable,
+    WritePermission,
+    DdlPermission,
+    LockAvailable,
 }
 
-impl<DB: Database + HasPromadRepo> Migrator<DB> {
-    /// Create a Migrator with an interactive UI that isn't thread safe
-    /// due to stdout being redirected while executing migrations.
-    pub fn create(pool: Pool<DB>) -> Self {
-        let cached = CachedPromadRepo::<DB, <DB as HasPromadRepo>::Repo>::new();
-        Self {
-            migrations: vec![],
-            pool,
-            repo: Box::new(cached),
-            ui_factory: Box::new(InteractiveMigrationUI::new),
-        }
-    }
+impl PreflightCheck {
+    pub const ALL: [PreflightCheck; 5] = [
+        PreflightCheck::Connection,
+        PreflightCheck::TrackingTable,
+        PreflightCheck::WritePermission,
+        PreflightCheck::DdlPermission,
+        PreflightCheck::LockAvailable,
+    ];
+}
 
-    /// Create a UI with a custom UI factory.
-    /// This is useful for testing or using a non-interactive
-    /// UI that's thread-safe.
-    pub fn create_with_ui(
-        pool: Pool<DB>,
-        ui_factory: Box<dyn Fn(&[(i64, &dyn Migration<DB>)]) -> Box<dyn MigrationUI>>,
-    ) -> Self {
-        let cached = CachedPromadRepo::<DB, <DB as HasPromadRepo>::Repo>::new();
-        Self {
-            migrations: vec![],
-            pool,
-            repo: Box::new(cached),
-            ui_factory,
-        }
+impl std::fmt::Display for PreflightCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            PreflightCheck::Connection => "connection",
+            PreflightCheck::TrackingTable => "tracking table",
+            PreflightCheck::WritePermission => "write permission",
+            PreflightCheck::DdlPermission => "DDL permission",
+            PreflightCheck::LockAvailable => "migration lock available",
+        };
+        f.write_str(description)
     }
 }
 
-impl<DB: Database> Migrator<DB> {
-    /// Add a single migration to the migrator.
-    pub fn add_migration(&mut self, migration: Box<dyn Migration<DB>>) {
-        self.migrations.push(migration);
+/// The fewest digits a timestamp can have, a `YYYYMMDD` date.
+const MIN_TIMESTAMP_DIGITS: usize = 8;
+
+/// Whether a migration timestamp is at or before a `YYYYMMDDHHMMSS` cutoff,
+/// comparing only as many leading digits as the shorter of the two has.
+/// Anything shorter than a date, such as the `1` of `1_create_users`, is a
+/// sequence number rather than a timestamp and never within the cutoff.
+fn timestamp_not_after(ts: i64, cutoff: &str) -> bool {
+    let ts = ts.to_string();
+    if ts.len() < MIN_TIMESTAMP_DIGITS {
+        return false;
     }
+    let len = ts.len().min(cutoff.len());
+    ts[..len] <= cutoff[..len]
+}
 
-    /// Add multiple migrations to the migrator.
-    pub fn add_migrations(&mut self, migrations: Vec<Box<dyn Migration<DB>>>) {
-        self.migrations.extend(migrations);
+/// The items of `a` that aren't in a longest common subsequence of `a` and
+/// `b`. Where there's a choice, earlier items of `a` are left out first.
+fn outside_common_subsequence<T: Clone + Eq + Hash>(a: &[T], b: &[T]) -> HashSet<T> {
+    // lengths[i][j] is the length of the longest common subsequence of
+    // a[i..] and b[j..].
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
     }
-
-    /// Remove a migration from the migrator by name.
-    pub fn remove_migration(&mut self, name: &str) {
-        self.migrations.retain(|x| x.name() != name);
+    let mut outside = HashSet::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() {
+        if j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if j < b.len() && lengths[i][j + 1] > lengths[i + 1][j] {
+            j += 1;
+        } else {
+            outside.insert(a[i].clone());
+            i += 1;
+        }
     }
+    outside
+}
 
-    /// Remove all migrations from the migrator.
-    pub fn remove_all_migrations(&mut self) {
-        self.migrations.clear();
+/// Reject names that can't be stored in the tracking table or that
+/// couldn't be told apart from another migration.
+fn validate_name(name: &str) -> crate::error::Result<()> {
+    let reason = if name.trim().is_empty() {
+        "name is empty"
+    } else if name.contains('\0') {
+        "name contains a null byte"
+    } else if name.chars().any(char::is_control) {
+        "name contains control characters"
+    } else {
+        return Ok(());
+    };
+    Err(error::Error::InvalidMigrationName {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// Reject role names that aren't plain identifiers, since they're
+/// interpolated into `SET ROLE`.
+fn validate_role(role: &str) -> crate::error::Result<()> {
+    let mut chars = role.chars();
+    let valid = matches!(chars.next(), Some(x) if x.is_ascii_alphabetic() || x == '_')
+        && chars.all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '$')
+        && role.len() <= 63;
+    if !valid {
+        return Err(error::Error::InvalidRoleName(role.to_string()));
+    }
+    Ok(())
+}
+
+/// Lines of `expected` missing from `actual`, prefixed with `-`, followed
+/// by lines of `actual` that aren't in `expected`, prefixed with `+`.
+#[cfg(feature = "testing")]
+fn schema_diff(expected: &[String], actual: &[String]) -> Vec<String> {
+    let missing = expected
+        .iter()
+        .filter(|x| !actual.contains(x))
+        .map(|x| format!("-{x}"));
+    let added = actual
+        .iter()
+        .filter(|x| !expected.contains(x))
+        .map(|x| format!("+{x}"));
+    missing.chain(added).collect()
+}
+
+/// Count a migration that ran and record how long it took.
+#[cfg(feature = "metrics")]
+fn record_migration(name: Cow<'static, str>, direction: Direction, elapsed: Duration) {
+    let direction = direction.as_str();
+    metrics::counter!("promad.migrations.applied", 1, "name" => name.clone(), "direction" => direction);
+    metrics::histogram!(
+        "promad.migration.duration_seconds",
+        elapsed.as_secs_f64(),
+        "name" => name,
+        "direction" => direction
+    );
+}
+
+/// Apply every pending migration in `migrations` with the default settings,
+/// without printing anything; progress is only logged through `tracing`.
+/// This is all most services need at startup; build a `Migrator` for
+/// anything else.
+pub async fn run_pending<DB: Database + HasPromadRepo>(
+    pool: Pool<DB>,
+    migrations: Vec<Box<dyn Migration<DB>>>,
+) -> crate::error::Result<ApplyReport> {
+    let started = Instant::now();
+    let mut migrator = Migrator::create(pool).with_progress_disabled();
+    migrator.add_migrations(migrations)?;
+    let applied = migrator.apply_pending().await?;
+    Ok(ApplyReport {
+        applied,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// The first migration recorded at an earlier time than the one before it,
+/// along with that one, which means the clocks of the machines that
+/// applied them disagree.
+fn clock_skew(rows: &[PromadRow]) -> Option<(&PromadRow, &PromadRow)> {
+    rows.windows(2)
+        .find(|x| x[1].created_at < x[0].created_at)
+        .map(|x| (&x[0], &x[1]))
+}
+
+/// The first 8 bytes of a SHA-256 over one `name<TAB>checksum` line per
+/// row, in hex.
+fn fingerprint(rows: &[PromadRow]) -> String {
+    let lines = rows
+        .iter()
+        .map(|row| {
+            format!(
+                "{}\t{}\n",
+                row.name,
+                row.checksum.as_deref().unwrap_or_default()
+            )
+        })
+        .collect::<String>();
+    hex::encode(&Checksum::sha256(lines).bytes[..8])
+}
+
+pub trait HasPromadRepo: Database {
+    type Repo: PromadRepo<Self>;
+}
+
+#[cfg(feature = "postgres")]
+impl HasPromadRepo for Postgres {
+    type Repo = PostgresPromadRepo;
+}
+
+#[cfg(feature = "any")]
+impl HasPromadRepo for sqlx::Any {
+    type Repo = repo::any::AnyPromadRepo;
+}
+
+impl<DB> Migrator<DB>
+where
+    DB: Database,
+    for<'c> &'c mut <DB as Database>::Connection: sqlx::Executor<'c, Database = DB>,
+    for<'q> <DB as sqlx::database::HasArguments<'q>>::Arguments: sqlx::IntoArguments<'q, DB>,
+{
+    /// Register the SQL migrations anywhere under `dir`. Each migration is
+    /// a `<name>.up.sql` file with an optional `<name>.down.sql`, ordered by
+    /// the number the name starts with.
+    pub fn add_migrations_from_dir(&mut self, dir: &std::path::Path) -> crate::error::Result<()> {
+        let migrations = sql::dir_migrations(dir)?
+            .into_iter()
+            .map(|x| Box::new(x) as Box<dyn Migration<DB>>)
+            .collect();
+        self.add_migrations(migrations)
+    }
+
+    /// Register the SQL migrations `source` loads, wherever it keeps them.
+    pub async fn add_migrations_from_source(
+        &mut self,
+        source: &dyn MigrationSource,
+    ) -> crate::error::Result<()> {
+        let migrations = source
+            .load()
+            .await?
+            .into_iter()
+            .map(|x| Box::new(x) as Box<dyn Migration<DB>>)
+            .collect();
+        self.add_migrations(migrations)
+    }
+
+    /// Register the SQL migrations in a directory embedded with
+    /// `include_dir!`, for binaries that can't rely on the filesystem.
+    /// Laid out like `add_migrations_from_dir`.
+    #[cfg(feature = "include_dir")]
+    pub fn add_migrations_from_embedded(
+        &mut self,
+        dir: &include_dir::Dir<'static>,
+    ) -> crate::error::Result<()> {
+        let migrations = sql::embedded_migrations(dir)?
+            .into_iter()
+            .map(|x| Box::new(x) as Box<dyn Migration<DB>>)
+            .collect();
+        self.add_migrations(migrations)
+    }
+}
+
+impl<DB: Database + HasPromadRepo> Migrator<DB> {
+    /// Create a Migrator with an interactive UI. Stdout is redirected while
+    /// executing migrations, so concurrent runs fall back to plain output.
+    pub fn create(pool: Pool<DB>) -> Self {
+        Self::create_with_ui(pool, Box::new(InteractiveMigrationUI::boxed))
+    }
+
+    /// Create a Migrator with an interactive UI drawn according to
+    /// `config`, e.g. with ASCII spinners for terminals that can't show
+    /// the default ones.
+    pub fn create_with_ui_config(pool: Pool<DB>, config: InteractiveUiConfig) -> Self {
+        Self::create_with_ui(
+            pool,
+            Box::new(move |migrations| {
+                InteractiveMigrationUI::boxed_with_config(migrations, &config)
+            }),
+        )
+    }
+
+    /// Create a UI with a custom UI factory.
+    /// This is useful for testing or using a non-interactive
+    /// UI that's thread-safe.
+    pub fn create_with_ui(pool: Pool<DB>, ui_factory: UiFactory<DB>) -> Self {
+        let cached = CachedPromadRepo::<DB, <DB as HasPromadRepo>::Repo>::new();
+        Self::create_with_repo(pool, Box::new(cached), ui_factory)
+    }
+
+    /// Create a Migrator around a single connection, for environments
+    /// without a pool. Only `apply_all` works: it runs every pending
+    /// migration in one transaction on the connection, which serves as
+    /// both the read and the write connection. Other operations fail with
+    /// `Error::PoolRequired`.
+    pub fn from_connection(conn: <DB as Database>::Connection) -> Self {
+        let cached = CachedPromadRepo::<DB, <DB as HasPromadRepo>::Repo>::new();
+        let mut migrator = Self::create_with_source(
+            None,
+            Box::new(cached),
+            Box::new(InteractiveMigrationUI::boxed),
+        );
+        migrator.connection = Some(futures_util::lock::Mutex::new(conn));
+        migrator
+    }
+}
+
+impl<DB: Database> Migrator<DB> {
+    /// Create a Migrator that tracks migrations with `repo`. Unlike the
+    /// other constructors the repo isn't wrapped in a `CachedPromadRepo`,
+    /// so wrap it yourself if you want caching.
+    pub fn create_with_repo(
+        pool: Pool<DB>,
+        repo: Box<dyn PromadRepo<DB>>,
+        ui_factory: UiFactory<DB>,
+    ) -> Self {
+        Self::create_with_source(Some(pool), repo, ui_factory)
+    }
+
+    fn create_with_source(
+        pool: Option<Pool<DB>>,
+        repo: Box<dyn PromadRepo<DB>>,
+        ui_factory: UiFactory<DB>,
+    ) -> Self {
+        Self {
+            migrations: vec![],
+            pool,
+            read_pool: None,
+            unlogged_pools: None,
+            connection: None,
+            repo,
+            ui_factory,
+            retry: RetryPolicy::default(),
+            force_irreversible: false,
+            dry_run: false,
+            lock_strategy: LockStrategy::default(),
+            ordering_strategy: OrderingStrategy::default(),
+            case_insensitive_names: false,
+            lock_watchdog: None,
+            actor: None,
+            isolation: None,
+            role: None,
+            skip_validation: false,
+            parallelism: 1,
+            acquire_timeout: None,
+            clock: Box::new(SystemClock),
+            timestamp_precision: TimestampPrecision::default(),
+            strict_revert: false,
+            no_op_down: NO_OP_DOWN,
+            tag_filter: None,
+            before_all: vec![],
+            after_all: vec![],
+            notify_channel: None,
+            skip_missing_on_revert: false,
+            numbered_names: None,
+            contiguous_ordering_keys: false,
+            clock_skew_warned: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<DB: Database> Migrator<DB> {
+    /// Retry connection acquisition and the migration transaction on transient
+    /// connection failures, with exponential backoff starting at `backoff`.
+    /// SQL errors are never retried, nor is a migration whose commit failed
+    /// since it may have been applied.
+    pub fn with_retry(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.retry = RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff,
+        };
+        self
+    }
+
+    /// Run `down` even for migrations that declare themselves irreversible.
+    pub fn with_force_irreversible(mut self, force: bool) -> Self {
+        self.force_irreversible = force;
+        self
+    }
+
+    /// Run migrations but roll back each one instead of committing it.
+    /// Note that later migrations won't see the changes of earlier ones.
+    /// Fails with `Error::DryRunUnsupported` on repos that can't roll back
+    /// what they record, such as `MemoryPromadRepo`.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Choose how concurrent migrators are kept from running at the same time.
+    pub fn with_lock_strategy(mut self, lock_strategy: LockStrategy) -> Self {
+        self.lock_strategy = lock_strategy;
+        self
+    }
+
+    /// Choose how the ordering keys of applied migrations are derived.
+    pub fn with_ordering_strategy(mut self, ordering_strategy: OrderingStrategy) -> Self {
+        self.ordering_strategy = ordering_strategy;
+        self
+    }
+
+    /// Log a warning naming the sessions a migration is waiting on whenever
+    /// it has been running for another `threshold`, so a migration blocked
+    /// on a lock doesn't just hang. Only supported on Postgres.
+    pub fn with_lock_watchdog(mut self, threshold: Duration) -> Self {
+        self.lock_watchdog = Some(threshold);
+        self
+    }
+
+    /// Don't check the applied history against the local migrations. Only
+    /// meant for recovering from an incident where that check is in the way.
+    /// Duplicate names are still rejected.
+    pub fn with_skip_validation(mut self, skip: bool) -> Self {
+        self.skip_validation = skip;
+        self
+    }
+
+    /// Run up to `n` migrations at once, each in its own transaction on its
+    /// own connections. A migration that declares `depends_on` waits only
+    /// for those, so it has to name every migration that touches the same
+    /// objects. One without waits for the migration before it, unless it's
+    /// `independent`, so existing histories still run in order. The pool
+    /// needs room for two connections per running migration, see
+    /// `with_acquire_timeout`.
+    ///
+    /// When a migration fails, the ones already running are finished, so
+    /// the history can be left with gaps. Validation allows for that while
+    /// parallelism is on.
+    pub fn with_parallelism(mut self, n: usize) -> Self {
+        self.parallelism = n.max(1);
+        self
+    }
+
+    /// Give up waiting for a connection from the pool after `timeout` with
+    /// `Error::AcquireTimeout`, which says what the connection was for.
+    /// Each migration holds a read and a write connection at once, plus
+    /// the lock connection for the whole run, so the pool needs at least
+    /// three connections or acquiring can wait forever. A migration saving
+    /// state borrows a fourth while it does, which waits for a free
+    /// connection like any other.
+    pub fn with_acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.acquire_timeout = Some(timeout);
+        self
+    }
+
+    /// Record applied migrations at the times `clock` gives rather than the
+    /// system time. Has no effect with `with_server_side_timestamps`.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Truncate the times applied migrations are recorded at to
+    /// `precision`, so a history compares equal after moving it to a
+    /// database that keeps less. Like `with_clock`, this has no effect
+    /// with `with_server_side_timestamps`.
+    pub fn with_timestamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.timestamp_precision = precision;
+        self
+    }
+
+    /// Fail with `Error::EmptyRevert` instead of recording a revert when a
+    /// migration's `down` changes neither the schema nor any rows, which
+    /// usually means it was never written. Migrations that really can't be
+    /// undone should return `false` from `reversible` instead.
+    pub fn with_strict_revert(mut self, strict: bool) -> Self {
+        self.strict_revert = strict;
+        self
+    }
+
+    /// Without `with_strict_revert`, warn that a reversible migration's
+    /// `down` may be a no-op when it returns faster than `threshold`, which
+    /// defaults to `NO_OP_DOWN`. Unlike the strict check this costs nothing,
+    /// but it's only a guess. `Duration::ZERO` turns the warning off.
+    pub fn with_no_op_down_threshold(mut self, threshold: Duration) -> Self {
+        self.no_op_down = threshold;
+        self
+    }
+
+    /// When reverting, skip applied migrations that no longer exist
+    /// locally with a warning, instead of failing validation because of
+    /// them. The rest of the history is still validated, so the migrations
+    /// that can be reverted are. Applied migrations are always matched to
+    /// local ones by name.
+    pub fn with_skip_missing_on_revert(mut self, skip: bool) -> Self {
+        self.skip_missing_on_revert = skip;
+        self
+    }
+
+    /// Only consider migrations tagged `tag`, and untagged ones, which are
+    /// shared by everyone. For services that share a migrations crate and
+    /// each run their own subset. Applied migrations the filter leaves out
+    /// are ignored when validating the history, so services can share the
+    /// tracking table too.
+    pub fn with_tag_filter(mut self, tag: &str) -> Self {
+        self.tag_filter = Some(tag.to_string());
+        self
+    }
+
+    /// Show no progress at all, using a `NullMigrationUI`. Meant for
+    /// services that embed promad, where the interactive UI's stdout
+    /// redirect would swallow the service's own output.
+    pub fn with_progress_disabled(mut self) -> Self {
+        self.ui_factory = Box::new(|_| Box::new(NullMigrationUI));
+        self
+    }
+
+    /// Statements run on every write connection the migrations use, before
+    /// its transaction begins, for session tuning that applies to the whole
+    /// run such as `SET maintenance_work_mem`. Where each migration gets a
+    /// connection of its own they run once per migration; `apply_group` and
+    /// migrators built with `from_connection` run them once. `apply_all_in`
+    /// doesn't run them, since the caller owns the session.
+    pub fn with_before_all(mut self, sql: Vec<String>) -> Self {
+        self.before_all = sql;
+        self
+    }
+
+    /// Statements run on the same connections as `with_before_all` once the
+    /// transaction has ended, even if a migration failed, to undo what they
+    /// changed before the connection goes back to the pool.
+    pub fn with_after_all(mut self, sql: Vec<String>) -> Self {
+        self.after_all = sql;
+        self
+    }
+
+    /// Send a Postgres notification on `channel`, with the migration's name
+    /// as the payload, each time a migration is applied or reverted. It's
+    /// sent from the migration's transaction so listeners only hear about
+    /// it once it commits. Ignored on other databases.
+    pub fn with_notify_channel(mut self, channel: &str) -> Self {
+        self.notify_channel = Some(channel.to_string());
+        self
+    }
+
+    /// Log every attempt to run a migration to `<table>_attempts`, outside
+    /// of the migration's transaction so failed attempts are kept too.
+    pub fn with_attempt_log(mut self, enabled: bool) -> Self {
+        self.repo.set_attempt_log(enabled);
+        self
+    }
+
+    /// Run the read and write transactions of each migration at `level`
+    /// instead of the database's default isolation level.
+    pub fn with_isolation(mut self, level: IsolationLevel) -> Self {
+        self.isolation = Some(level);
+        self
+    }
+
+    /// Run migrations and promad's own DDL as `role`, for when the pool
+    /// connects as a role that can't change the schema. The role is set with
+    /// `SET LOCAL ROLE` in each transaction, so connections go back to the
+    /// pool as the role they connected as. The connecting role has to be a
+    /// member of `role`.
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.role = Some(role.to_string());
+        self
+    }
+
+    /// Record `actor` as whoever applied migrations. Without this the
+    /// `PROMAD_ACTOR` environment variable is used, then the OS username.
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.to_string());
+        self
+    }
+
+    /// Track applied migrations in `table_name` instead of `_promad`. The
+    /// name may be schema qualified, e.g. `app.migrations`.
+    pub fn with_table_name(mut self, table_name: &str) -> Self {
+        self.repo.set_table_name(table_name);
+        self
+    }
+
+    /// Treat migration names that only differ in case as duplicates, for
+    /// databases whose collation compares them as equal. Names in the
+    /// history are matched to local migrations the same way.
+    pub fn with_case_insensitive_names(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive_names = case_insensitive;
+        self
+    }
+
+    /// Require every migration name to start with `digits` digits and an
+    /// underscore, like `0001_create_users`.
+    pub fn with_numbered_names(mut self, digits: usize) -> Self {
+        self.numbered_names = Some(digits);
+        self
+    }
+
+    /// Require the ordering keys of the migrations to follow each other
+    /// without gaps, like 1, 2, 3, so a migration missing from the list is
+    /// caught. Off by default since gaps between declared keys leave room
+    /// to insert migrations later.
+    pub fn with_contiguous_ordering_keys(mut self, contiguous: bool) -> Self {
+        self.contiguous_ordering_keys = contiguous;
+        self
+    }
+
+    /// Log every statement executed while migrating, like `psql -e`.
+    /// Statements run by migrations are logged by sqlx under the
+    /// `sqlx::query` target and promad's own bookkeeping under `promad::sql`,
+    /// both at `INFO`. Either way a `tracing` subscriber or `log` logger has
+    /// to be installed to see them.
+    ///
+    /// The pools passed in are left alone: the migrator connects through
+    /// copies of them with statement logging turned on, which count towards
+    /// the database's connection limit separately. Migrators created with
+    /// `from_connection` only log promad's own bookkeeping.
+    pub fn with_sql_logging(mut self, enabled: bool) -> Self {
+        self.repo.set_sql_logging(enabled);
+        match (enabled, self.unlogged_pools.take()) {
+            (true, None) => {
+                let logged = |pool: &Pool<DB>| {
+                    let options = (*pool.connect_options())
+                        .clone()
+                        .log_statements(log::LevelFilter::Info);
+                    pool.options().clone().connect_lazy_with(options)
+                };
+                let pool = self.pool.as_ref().map(logged);
+                let read_pool = self.read_pool.as_ref().map(logged);
+                self.unlogged_pools = Some((
+                    std::mem::replace(&mut self.pool, pool),
+                    std::mem::replace(&mut self.read_pool, read_pool),
+                ));
+            }
+            (true, unlogged) => self.unlogged_pools = unlogged,
+            (false, Some((pool, read_pool))) => {
+                self.pool = pool;
+                self.read_pool = read_pool;
+            }
+            (false, None) => {}
+        }
+        self
+    }
+
+    /// Acquire the read connection migrations get from `pool`, such as one
+    /// to a read replica, rather than from the primary. It's still made read
+    /// only. The tracking table is always read from the primary, since a
+    /// replica may lag behind it.
+    pub fn with_read_pool(mut self, pool: Pool<DB>) -> Self {
+        // Start over so that the new read pool gets a logging copy too.
+        let logging = self.unlogged_pools.is_some();
+        self = self.with_sql_logging(false);
+        self.read_pool = Some(pool);
+        self.with_sql_logging(logging)
+    }
+
+    /// Record when migrations ran using the database's clock rather than
+    /// this machine's, for hosts whose clock drifts from the server.
+    pub fn with_server_side_timestamps(mut self, enabled: bool) -> Self {
+        self.repo.set_server_side_timestamps(enabled);
+        self
+    }
+
+    /// Close the pool, waiting for its connections to be closed, so a short
+    /// lived process can exit promptly. Clones of the pool passed to the
+    /// migrator are closed as well.
+    pub async fn close(self) {
+        let (unlogged_pool, unlogged_read_pool) = self.unlogged_pools.unwrap_or_default();
+        for pool in [self.pool, self.read_pool, unlogged_pool, unlogged_read_pool]
+            .into_iter()
+            .flatten()
+        {
+            pool.close().await;
+        }
+        if let Some(conn) = self.connection {
+            let _ = conn.into_inner().close().await;
+        }
+    }
+
+    /// Add a single migration to the migrator.
+    /// Fails if its name is invalid or collides with one already added, or
+    /// if `validate_local` fails with it added.
+    pub fn add_migration(&mut self, migration: Box<dyn Migration<DB>>) -> crate::error::Result<()> {
+        self.add_migrations(vec![migration])
+    }
+
+    /// Add multiple migrations to the migrator. Either all of them are
+    /// added or, if any name is invalid or collides or `validate_local`
+    /// fails with them added, none are.
+    ///
+    /// The checks use the configuration at the time, so call the builders
+    /// `validate_local` depends on, such as `with_ordering_strategy`,
+    /// `with_numbered_names` and `with_case_insensitive_names`, before
+    /// adding migrations. Migrations added earlier are still checked against
+    /// the final configuration by every operation, only later.
+    pub fn add_migrations(
+        &mut self,
+        migrations: Vec<Box<dyn Migration<DB>>>,
+    ) -> crate::error::Result<()> {
+        let names = migrations.iter().map(|x| x.name()).collect::<Vec<_>>();
+        self.validate_new_names(names.iter().map(|x| &**x))?;
+        let len = self.migrations.len();
+        self.migrations.extend(migrations);
+        if let Err(e) = self.validate_local() {
+            self.migrations.truncate(len);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Remove a migration from the migrator by name.
+    pub fn remove_migration(&mut self, name: &str) {
+        let name = self.normalize_name(name);
+        let idx = self
+            .migrations
+            .iter()
+            .position(|x| self.normalize_name(&x.name()) == name);
+        if let Some(idx) = idx {
+            self.migrations.remove(idx);
+        }
+    }
+
+    /// Remove all migrations from the migrator.
+    pub fn remove_all_migrations(&mut self) {
+        self.migrations.clear();
     }
 
     /// Applies migrations up to and including the migration with the given name.
     pub async fn apply_to_inclusive(&self, up_to_name: &str) -> crate::error::Result<()> {
+        // Checked before touching the database so a typo doesn't create the
+        // tracking table or wait for the lock.
+        if self.migration_named(up_to_name).is_none() {
+            return Err(error::Error::NoSuchMigration(up_to_name.to_string()));
+        }
+
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            // Everything pending up to the target's position, whether or not
+            // the target itself is pending and however the pending set is
+            // ordered.
+            let ordered = self.ordered_migrations()?;
+            let target_name = self.normalize_name(up_to_name);
+            let target = ordered
+                .iter()
+                .position(|(_, x)| self.normalize_name(&x.name()) == target_name)
+                .ok_or_else(|| error::Error::NoSuchMigration(up_to_name.to_string()))?;
+            let up_to_target = ordered[..=target]
+                .iter()
+                .map(|(_, x)| x.name())
+                .collect::<HashSet<_>>();
+            let migrations_to_run = self
+                .find_unapplied()
+                .await?
+                .into_iter()
+                .filter(|(_, x)| up_to_target.contains(&x.name()))
+                .collect();
+
+            self.apply_migrations(migrations_to_run, Direction::Up)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Apply all unapplied migrations up to and including the one with the
+    /// given ordering key.
+    pub async fn apply_through_key(&self, key: i64) -> crate::error::Result<()> {
+        self.check_ordering_key(key)?;
+
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            let migrations_to_run = self
+                .find_unapplied()
+                .await?
+                .into_iter()
+                .filter(|(ordering_key, _)| *ordering_key <= key)
+                .collect();
+            self.apply_migrations(migrations_to_run, Direction::Up)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Apply the unapplied migrations whose `timestamp()` is at or before
+    /// `cutoff`, stopping at the first one that's later or has no timestamp
+    /// so the applied history stays contiguous. Timestamps are compared as
+    /// `YYYYMMDDHHMMSS` digits, truncated to as many digits as the
+    /// migration's timestamp has, so a `20240101_` prefix counts as midnight.
+    /// Digits past the seconds are ignored. Prefixes shorter than a date are
+    /// sequence numbers, not timestamps, so they stop the run too.
+    pub async fn apply_until_timestamp(
+        &self,
+        cutoff: chrono::DateTime<Utc>,
+    ) -> crate::error::Result<()> {
+        let cutoff = cutoff.format("%Y%m%d%H%M%S").to_string();
+
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            let migrations_to_run = self
+                .find_unapplied()
+                .await?
+                .into_iter()
+                .take_while(|(_, x)| {
+                    x.timestamp()
+                        .is_some_and(|ts| timestamp_not_after(ts, &cutoff))
+                })
+                .collect();
+            self.apply_migrations(migrations_to_run, Direction::Up)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Number of migrations added to the migrator.
+    pub fn migration_count(&self) -> usize {
+        self.migrations.len()
+    }
+
+    /// Number of migrations that haven't been applied yet. Cheap enough for
+    /// a health check: it doesn't create the tracking table, take the lock
+    /// or validate the history.
+    pub async fn pending_count(&self) -> crate::error::Result<usize> {
+        let mut conn = self.acquire("read").await?;
+        let exists = self.repo.exists(&mut conn).await?;
+        // Reading the history acquires a connection of its own, which a pool
+        // of one could never hand out while this one is held.
+        drop(conn);
+        let count = if exists {
+            self.repo.invalidate()?;
+            self.find_unapplied().await?.len()
+        } else {
+            self.selected_migrations().count()
+        };
+        #[cfg(feature = "metrics")]
+        metrics::gauge!("promad.migrations.pending", count as f64);
+        Ok(count)
+    }
+
+    /// Whether the migration named `name` has been applied, for code that
+    /// only wants to act once a migration has run. Like `pending_count` it
+    /// doesn't create the tracking table, take the lock or validate the
+    /// history, and a name no local migration has is looked up all the same.
+    pub async fn is_applied(&self, name: &str) -> crate::error::Result<bool> {
+        let mut conn = self.acquire("read").await?;
+        if !self.repo.exists(&mut conn).await? {
+            return Ok(false);
+        }
+        // Another process may have applied it since the cache was loaded, so
+        // go to the database.
+        if !self.case_insensitive_names {
+            return Ok(self.repo.get_uncached(name, &mut conn).await?.is_some());
+        }
+        // The names can't be looked up directly when case doesn't count.
+        let name = self.normalize_name(name);
+        Ok(self
+            .repo
+            .get_all(&mut conn)
+            .await?
+            .iter()
+            .any(|x| self.normalize_name(&x.name) == name))
+    }
+
+    /// The migrations that haven't been applied yet, in the order they'd be
+    /// applied. The history is validated first, so a mismatch is an error
+    /// rather than a misleading list.
+    pub async fn pending(&self) -> crate::error::Result<Vec<PendingMigration>> {
         self.init_sql().await?;
         self.validate_all().await?;
-        if !self
+
+        Ok(self
+            .find_unapplied()
+            .await?
+            .into_iter()
+            .map(|(ordering_key, migration)| PendingMigration {
+                name: migration.name(),
+                ordering_key,
+            })
+            .collect())
+    }
+
+    /// Every attempt to run a migration, oldest first, including the ones
+    /// that failed. Empty unless `with_attempt_log` is on.
+    pub async fn attempts(&self) -> crate::error::Result<Vec<repo::PromadAttempt>> {
+        self.init_sql().await?;
+        let mut conn = self.acquire("read").await?;
+        self.repo.attempts(&mut conn).await
+    }
+
+    /// The applied migrations, newest first, which is the order they'd be
+    /// reverted in. Like `pending`, the history is validated first.
+    pub async fn applied(&self) -> crate::error::Result<Vec<MigrationStatus>> {
+        self.validate_local()?;
+        self.init_sql().await?;
+
+        let mut read = self.acquire("read").await?;
+        let rows = self.repo.get_all(&mut read).await?;
+        self.validate_applied(&rows).await?;
+        Ok(rows
+            .into_iter()
+            .rev()
+            .filter_map(|row| {
+                let migration = self.migration_named(&row.name)?;
+                Some(MigrationStatus {
+                    name: migration.name(),
+                    ordering_key: row.ordering_key,
+                    applied_at: row.created_at,
+                    description: migration.description().map(str::to_string),
+                })
+            })
+            .collect())
+    }
+
+    /// Every row of the tracking table, oldest first, without comparing them
+    /// to the local migrations. Works without any migrations added, for
+    /// inspecting a database without its migration code. Nothing is
+    /// created, so this fails if the tracking table doesn't exist.
+    pub async fn db_migrations(&self) -> crate::error::Result<Vec<PromadRow>> {
+        self.repo.invalidate()?;
+        let mut read = self.acquire("read").await?;
+        self.repo.get_all(&mut read).await
+    }
+
+    /// Every row of the tracking table, oldest first, for recording the same
+    /// history in another database with `import_history`. Empty if the
+    /// table doesn't exist.
+    pub async fn export_history(&self) -> crate::error::Result<Vec<PromadRow>> {
+        self.repo.invalidate()?;
+        let mut read = self.acquire("read").await?;
+        if !self.repo.exists(&mut read).await? {
+            return Ok(vec![]);
+        }
+        self.repo.get_all(&mut read).await
+    }
+
+    /// Record `rows` from `export_history` as the applied migrations
+    /// without running anything, e.g. after restoring a dump of another
+    /// database. The rows are validated against the local migrations
+    /// first. An existing history is only replaced if `force` is set.
+    /// Nothing is committed on a dry run.
+    pub async fn import_history(
+        &self,
+        rows: &[PromadRow],
+        force: bool,
+    ) -> crate::error::Result<()> {
+        self.validate_local()?;
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_applied(rows).await?;
+
+            let mut conn = self.acquire("write").await?;
+            let mut txn = self.begin_write(&mut conn).await?;
+            let existing = self.repo.get_all(&mut txn).await?;
+            if !existing.is_empty() {
+                if !force {
+                    return Err(error::Error::HistoryNotEmpty(existing.len()));
+                }
+                self.repo.clear(&mut txn).await?;
+            }
+            self.repo.insert_many(rows, &mut txn).await?;
+            if self.dry_run {
+                // The imported rows are cached, but rolled back.
+                self.repo.invalidate()?;
+            } else {
+                txn.commit().await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// The applied migrations that aren't among the local ones, such as
+    /// migrations whose files were deleted on another branch. These are
+    /// what `Error::DeletedMigrations` complains about. Nothing is created,
+    /// so a database without the tracking table has none.
+    pub async fn orphaned(&self) -> crate::error::Result<Vec<PromadRow>> {
+        self.repo.invalidate()?;
+        let mut read = self.acquire("read").await?;
+        if !self.repo.exists(&mut read).await? {
+            return Ok(vec![]);
+        }
+        let local = self
             .migrations
             .iter()
-            .map(|x| x.name())
-            .any(|x| x == up_to_name)
-        {
-            return Err(error::Error::NoSuchMigration(up_to_name.to_string()));
-        }
+            .map(|x| self.normalize_name(&x.name()))
+            .collect::<HashSet<_>>();
+        Ok(self
+            .repo
+            .get_all(&mut read)
+            .await?
+            .into_iter()
+            .filter(|row| !local.contains(&self.normalize_name(&row.name)))
+            .collect())
+    }
 
-        let unapplied_migrations = self.find_unapplied().await?;
+    /// Every way the applied history differs from the local migrations,
+    /// rather than the first one validation stops at, in history order.
+    /// Pending migrations aren't drift. Nothing is created, so a database
+    /// without the tracking table has none.
+    pub async fn drift(&self) -> crate::error::Result<Vec<Drift>> {
+        self.repo.invalidate()?;
+        let mut read = self.acquire("read").await?;
+        if !self.repo.exists(&mut read).await? {
+            return Ok(vec![]);
+        }
+        let rows = self
+            .repo
+            .get_all(&mut read)
+            .await?
+            .into_iter()
+            .filter(|row| self.selected_row(row))
+            .collect::<Vec<_>>();
+        let local = self.ordered_migrations()?;
+        let local_positions = local
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, x))| (self.normalize_name(&x.name()), idx))
+            .collect::<HashMap<_, _>>();
+        let applied = rows
+            .iter()
+            .map(|row| self.normalize_name(&row.name))
+            .collect::<HashSet<_>>();
 
-        let mut migrations_to_run = Vec::new();
+        // Compare the order of the migrations that are both applied and
+        // local. Those in their longest common subsequence are in order
+        // relative to each other, and the rest were moved.
+        let applied_order = rows
+            .iter()
+            .map(|row| self.normalize_name(&row.name))
+            .filter(|name| local_positions.contains_key(name))
+            .collect::<Vec<_>>();
+        let local_order = local
+            .iter()
+            .map(|(_, x)| self.normalize_name(&x.name()))
+            .filter(|name| applied.contains(name))
+            .collect::<Vec<_>>();
+        let reordered = outside_common_subsequence(&applied_order, &local_order);
 
-        for (ordering_key, unapplied) in unapplied_migrations.into_iter() {
-            migrations_to_run.push((ordering_key, unapplied));
-            // self.apply_one_internal(unapplied, idx as i64).await?;
-            if unapplied.name() == up_to_name {
-                break;
+        // Unapplied local migrations by checksum, for spotting renames.
+        let mut unapplied = local
+            .iter()
+            .filter(|(_, x)| !applied.contains(&self.normalize_name(&x.name())))
+            .filter_map(|(_, x)| Some((x.checksum()?.to_string(), x.name().to_string())))
+            .collect::<HashMap<_, _>>();
+
+        let mut drift = vec![];
+        for (idx, row) in rows.iter().enumerate() {
+            let name = self.normalize_name(&row.name);
+            let Some(&local_position) = local_positions.get(&name) else {
+                match row.checksum.as_ref().and_then(|x| unapplied.remove(x)) {
+                    Some(local) => drift.push(Drift::Renamed {
+                        applied: row.name.clone(),
+                        local,
+                    }),
+                    None => drift.push(Drift::Orphaned {
+                        name: row.name.clone(),
+                    }),
+                }
+                continue;
+            };
+            if reordered.contains(&name) {
+                drift.push(Drift::Reordered {
+                    name: row.name.clone(),
+                    applied_position: idx,
+                    local_position,
+                });
+            }
+            // A checksum from a different algorithm only needs restamping.
+            let applied = row.checksum.as_deref().and_then(Checksum::parse);
+            if let (Some(applied), Some(local)) = (applied, local[local_position].1.checksum()) {
+                if applied.algo == local.algo && applied.bytes != local.bytes {
+                    drift.push(Drift::Edited {
+                        name: row.name.clone(),
+                        applied: applied.to_string(),
+                        local: local.to_string(),
+                    });
+                }
             }
         }
+        Ok(drift)
+    }
 
-        self.apply_migrations(migrations_to_run, Direction::Up)
-            .await?;
-        Ok(())
+    /// A short hash of the applied migrations in order, with their
+    /// checksums where they have one. Databases with the same migrations
+    /// applied have the same fingerprint, for checking that an environment
+    /// is at the expected version. Nothing is created, and a database
+    /// without the tracking table has the fingerprint of no migrations.
+    pub async fn schema_fingerprint(&self) -> crate::error::Result<String> {
+        self.repo.invalidate()?;
+        let mut read = self.acquire("read").await?;
+        let rows = if self.repo.exists(&mut read).await? {
+            self.repo.get_all(&mut read).await?
+        } else {
+            vec![]
+        };
+        Ok(fingerprint(&rows))
+    }
+
+    /// The SQL the named migration would run, without running it. Only
+    /// migrations with fixed SQL such as `SqlFileMigration` can be rendered.
+    pub fn render(&self, name: &str) -> crate::error::Result<RenderedMigration> {
+        let migration = self
+            .migration_named(name)
+            .ok_or_else(|| error::Error::NoSuchMigration(name.to_string()))?;
+        migration
+            .render()
+            .ok_or_else(|| error::Error::NotRenderable(name.to_string()))
+    }
+
+    /// Whether any migrations haven't been applied yet. See `pending_count`.
+    pub async fn has_pending(&self) -> crate::error::Result<bool> {
+        Ok(self.pending_count().await? > 0)
+    }
+
+    /// Apply the next `count` unapplied migrations, or all of them if fewer are pending.
+    pub async fn apply_n(&self, count: usize) -> crate::error::Result<()> {
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            let mut unapplied_migrations = self.find_unapplied().await?;
+            unapplied_migrations.truncate(count);
+            self.apply_migrations(unapplied_migrations, Direction::Up)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Revert the last `count` applied migrations, or all of them if fewer are applied.
+    pub async fn revert_n(&self, count: usize) -> crate::error::Result<()> {
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_for_revert().await?;
+
+            let mut applied_migrations = self.find_applied().await?;
+            applied_migrations.truncate(count);
+            self.apply_migrations(applied_migrations, Direction::Down)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Find all applied migrations from the tracking table, most recent first.
+    async fn find_applied(&self) -> crate::error::Result<Vec<(i64, &dyn Migration<DB>)>> {
+        let mut read = self.acquire("read").await?;
+        Ok(self
+            .repo
+            .get_all(&mut read)
+            .await?
+            .iter()
+            .rev()
+            .filter_map(|x| {
+                let migration = self.migration_named(&x.name);
+                if migration.is_none() && self.selected_row(x) {
+                    tracing::warn!("can't revert {}, no local down script", x.name);
+                }
+                Some((x.ordering_key, migration?))
+            })
+            .collect())
     }
 
     /// Find all unapplied migrations from the tracking table.
     async fn find_unapplied(&self) -> crate::error::Result<Vec<(i64, &dyn Migration<DB>)>> {
-        let mut read = self.pool.acquire().await?;
+        let mut read = self.acquire("read").await?;
         let applied_names = self
             .repo
             .get_all(&mut read)
             .await?
-            .into_iter()
-            .map(|x| x.name)
+            .iter()
+            .map(|x| self.normalize_name(&x.name))
             .collect::<HashSet<_>>();
 
         Ok(self
-            .migrations
-            .iter()
-            .map(|x| &**x)
-            .enumerate()
-            .map(|(x, y)| (x as i64, y))
-            .filter(|(_, x)| !applied_names.contains(x.name()))
+            .ordered_migrations()?
+            .into_iter()
+            .filter(|(_, x)| !applied_names.contains(&self.normalize_name(&x.name())))
             .collect())
     }
 
@@ -306,122 +2132,1101 @@ impl<DB: Database> Migrator<DB> {
         migrations: Vec<(i64, &dyn Migration<DB>)>,
         direction: Direction,
     ) -> crate::error::Result<()> {
+        self.check_pool_size()?;
+        if direction == Direction::Down && !self.force_irreversible {
+            if let Some((_, migration)) = migrations.iter().find(|(_, x)| !x.reversible()) {
+                return Err(error::Error::IrreversibleMigration(
+                    migration.name().to_string(),
+                ));
+            }
+        }
+
         let ui = (*self.ui_factory)(&migrations);
+        let started = Instant::now();
 
-        for (idx, (ordering_key, migration)) in migrations.iter().enumerate() {
-            ui.start(idx, &direction);
-            match &direction {
-                Direction::Up => {
-                    self.apply_one_internal(*migration, *ordering_key).await?;
-                }
-                Direction::Down => {
-                    self.revert_one_internal(*migration).await?;
-                }
+        if self.parallelism > 1 {
+            self.run_parallel(&*ui, &migrations, direction).await?;
+        } else {
+            for (idx, &migration) in migrations.iter().enumerate() {
+                self.run_tracked(&*ui, idx, migration, direction).await?;
             }
-            ui.finish(idx);
         }
 
         if migrations.len() > 0 {
-            ui.complete();
+            ui.complete(&RunSummary {
+                count: migrations.len(),
+                direction,
+                elapsed: started.elapsed(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Run `with_before_all` or `with_after_all` statements on a write
+    /// connection, outside any transaction.
+    async fn run_write_hooks(
+        &self,
+        statements: &[String],
+        write: &mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        for sql in statements {
+            self.repo.execute(sql, write).await?;
+        }
+        Ok(())
+    }
+
+    /// Apply each pending migration, revert it and apply it again, checking
+    /// that the schema after reverting matches the one before applying and
+    /// that applying again gives the same schema as the first time. Meant
+    /// for a scratch database, since every migration ends up applied.
+    ///
+    /// Testing stops at the first migration that isn't symmetric, because
+    /// applying it again would run against whatever its `down` left behind.
+    #[cfg(feature = "testing")]
+    pub async fn test_reversibility(&self) -> crate::error::Result<Vec<ReversibilityResult>> {
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+            self.check_pool_size()?;
+
+            let mut results = vec![];
+            for (ordering_key, migration) in self.find_unapplied().await? {
+                let before = self.schema_snapshot().await?;
+                self.run_one(
+                    migration,
+                    ordering_key,
+                    Direction::Up,
+                    &NoopProgressReporter,
+                )
+                .await?;
+                let mut result = ReversibilityResult {
+                    name: migration.name(),
+                    reversible: migration.reversible(),
+                    differences: vec![],
+                };
+                if !result.reversible {
+                    results.push(result);
+                    continue;
+                }
+
+                let applied = self.schema_snapshot().await?;
+                self.run_one(
+                    migration,
+                    ordering_key,
+                    Direction::Down,
+                    &NoopProgressReporter,
+                )
+                .await?;
+                result.differences = schema_diff(&before, &self.schema_snapshot().await?);
+                if !result.is_symmetric() {
+                    results.push(result);
+                    break;
+                }
+                self.run_one(
+                    migration,
+                    ordering_key,
+                    Direction::Up,
+                    &NoopProgressReporter,
+                )
+                .await?;
+                result.differences = schema_diff(&applied, &self.schema_snapshot().await?);
+                results.push(result);
+            }
+            Ok(results)
+        })
+        .await
+    }
+
+    /// Snapshot the schema through a connection of its own.
+    #[cfg(feature = "testing")]
+    async fn schema_snapshot(&self) -> crate::error::Result<Vec<String>> {
+        let mut read = self.acquire("read").await?;
+        self.repo.schema_snapshot(&mut read).await
+    }
+
+    /// Fail if the pool can't hand out every connection a run holds at
+    /// once: a read and a write connection per running migration, plus one
+    /// for each migration's lock watchdog if there is one, plus the lock
+    /// connection. Otherwise acquiring would wait forever. With a read pool
+    /// the read connections come from it instead. Migrations that save state
+    /// borrow another connection for it only while they do, under the
+    /// acquire timeout, so they aren't counted.
+    fn check_pool_size(&self) -> crate::error::Result<()> {
+        let lock = usize::from(self.lock_strategy != LockStrategy::None);
+        let reads = match &self.read_pool {
+            Some(read_pool) => {
+                let configured = read_pool.options().get_max_connections() as usize;
+                if configured < self.parallelism {
+                    return Err(error::Error::PoolTooSmall {
+                        needed: self.parallelism,
+                        configured,
+                    });
+                }
+                0
+            }
+            None => self.parallelism,
+        };
+        let watchdogs = if self.lock_watchdog.is_some() {
+            self.parallelism
+        } else {
+            0
+        };
+        let needed = self.parallelism + reads + watchdogs + lock;
+        let configured = self.pool()?.options().get_max_connections() as usize;
+        if configured < needed {
+            return Err(error::Error::PoolTooSmall { needed, configured });
         }
+        Ok(())
+    }
 
+    /// Run the migration at `idx` of a run, keeping the UI and the attempt
+    /// log up to date.
+    async fn run_tracked(
+        &self,
+        ui: &dyn MigrationUI,
+        idx: usize,
+        (ordering_key, migration): (i64, &dyn Migration<DB>),
+        direction: Direction,
+    ) -> crate::error::Result<()> {
+        ui.start(idx, &direction);
+        let progress = ui.progress(idx);
+        let migration_started = Instant::now();
+        let attempt = self.start_attempt(migration, direction).await?;
+        let res = self
+            .run_one(migration, ordering_key, direction, &*progress)
+            .await;
+        let logged = match attempt {
+            Some(id) => self.finish_attempt(id, res.as_ref().err()).await,
+            None => Ok(()),
+        };
+        // The migration's own error matters more than failing to log it.
+        if let Err(e) = res {
+            ui.fail(idx, &e);
+            return Err(e);
+        }
+        logged?;
+        ui.finish(idx);
+        let elapsed = migration_started.elapsed();
+        let verb = match direction {
+            Direction::Up => "applied",
+            Direction::Down => "reverted",
+        };
+        tracing::info!("{verb} migration {} in {:?}", migration.name(), elapsed);
+        #[cfg(feature = "metrics")]
+        record_migration(migration.name(), direction, elapsed);
         Ok(())
     }
 
+    /// Run migrations concurrently, up to the parallelism limit, starting
+    /// each once everything it has to wait for in the run has finished.
+    /// After a failure nothing new is started, but the migrations already
+    /// running are waited for.
+    async fn run_parallel(
+        &self,
+        ui: &dyn MigrationUI,
+        migrations: &[(i64, &dyn Migration<DB>)],
+        direction: Direction,
+    ) -> crate::error::Result<()> {
+        let waits_on = self.run_dependencies(migrations, direction);
+        let mut unmet = waits_on.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut unblocks = vec![vec![]; migrations.len()];
+        for (idx, waits_on) in waits_on.iter().enumerate() {
+            for &other in waits_on {
+                unblocks[other].push(idx);
+            }
+        }
+        let mut ready = (0..migrations.len())
+            .filter(|&idx| unmet[idx] == 0)
+            .collect::<BTreeSet<_>>();
+
+        let mut running = FuturesUnordered::new();
+        let mut first_error = None;
+        loop {
+            while first_error.is_none() && running.len() < self.parallelism {
+                let Some(idx) = ready.pop_first() else {
+                    break;
+                };
+                running.push(async move {
+                    let res = self.run_tracked(ui, idx, migrations[idx], direction).await;
+                    (idx, res)
+                });
+            }
+            let Some((idx, res)) = running.next().await else {
+                break;
+            };
+            match res {
+                Ok(()) => {
+                    for &other in &unblocks[idx] {
+                        unmet[other] -= 1;
+                        if unmet[other] == 0 {
+                            ready.insert(other);
+                        }
+                    }
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// For each migration of a run, the earlier migrations in the run it
+    /// has to wait for. Applying waits for dependencies and reverting waits
+    /// for dependents. Migrations without dependencies depend on the one
+    /// before them unless they're `independent`. Anything outside of the
+    /// run is already in place.
+    fn run_dependencies(
+        &self,
+        migrations: &[(i64, &dyn Migration<DB>)],
+        direction: Direction,
+    ) -> Vec<Vec<usize>> {
+        let positions = migrations
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, x))| (self.normalize_name(&x.name()), idx))
+            .collect::<HashMap<_, _>>();
+        let mut waits_on = vec![vec![]; migrations.len()];
+        for (idx, (_, migration)) in migrations.iter().enumerate() {
+            if migration.depends_on().is_empty() && !migration.independent() {
+                // Runs are in the order of their direction, so when
+                // reverting the migration before this one comes after it.
+                match direction {
+                    Direction::Up if idx > 0 => waits_on[idx].push(idx - 1),
+                    Direction::Down if idx + 1 < migrations.len() => waits_on[idx + 1].push(idx),
+                    _ => {}
+                }
+            }
+            for dependency in migration.depends_on() {
+                let Some(&dep_idx) = positions.get(&self.normalize_name(dependency)) else {
+                    continue;
+                };
+                match direction {
+                    Direction::Up if dep_idx < idx => waits_on[idx].push(dep_idx),
+                    Direction::Down if idx < dep_idx => waits_on[dep_idx].push(idx),
+                    _ => {}
+                }
+            }
+        }
+        waits_on
+    }
+
     /// Apply all migrations that haven't been applied yet.
     pub async fn apply_all(&self) -> crate::error::Result<()> {
+        self.apply_pending().await?;
+        Ok(())
+    }
+
+    /// Apply all migrations that haven't been applied yet, returning their
+    /// names.
+    async fn apply_pending(&self) -> crate::error::Result<Vec<Cow<'static, str>>> {
+        if let Some(conn) = &self.connection {
+            return self.apply_on_connection(conn).await;
+        }
         self.init_sql().await?;
-        self.validate_all().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            let unapplied_migrations = self.find_unapplied().await?;
+            let names = unapplied_migrations.iter().map(|(_, x)| x.name()).collect();
+            self.apply_migrations(unapplied_migrations, Direction::Up)
+                .await?;
+            Ok(names)
+        })
+        .await
+    }
 
-        let unapplied_migrations = self.find_unapplied().await?;
-        self.apply_migrations(unapplied_migrations, Direction::Up)
-            .await?;
+    /// Apply all migrations that haven't been applied yet in the caller's
+    /// transaction, recording them in it too. Nothing is committed, so the
+    /// caller decides whether the migrations stick. Every migration has to
+    /// be `transactional`.
+    ///
+    /// Session level advisory locks are taken at the transaction level
+    /// instead, so the lock is held until the caller commits. Migrations
+    /// read through a separate read only connection, which doesn't see
+    /// what earlier migrations changed in the transaction. Nothing is
+    /// retried or written to the attempt log, and the `with_before_all`
+    /// and `with_after_all` statements aren't run.
+    pub async fn apply_all_in(&self, txn: &mut Transaction<'_, DB>) -> crate::error::Result<()> {
+        if let Some(migration) = self.selected_migrations().find(|x| !x.transactional()) {
+            return Err(error::Error::NonTransactionalMigration(
+                migration.name().to_string(),
+            ));
+        }
+        self.validate_local()?;
+
+        // The caller may roll back, so nothing written here can be cached.
+        self.repo.invalidate()?;
+        let res = self.apply_all_in_txn(txn, false).await;
+        self.repo.invalidate()?;
+        res.map(drop)
+    }
+
+    /// `apply_all` for a migrator built with `from_connection`. Works like
+    /// `apply_all_in` with a transaction of its own, which is committed.
+    async fn apply_on_connection(
+        &self,
+        conn: &futures_util::lock::Mutex<<DB as Database>::Connection>,
+    ) -> crate::error::Result<Vec<Cow<'static, str>>> {
+        self.check_dry_run()?;
+        for migration in self.selected_migrations() {
+            if !migration.transactional() {
+                return Err(error::Error::NonTransactionalMigration(
+                    migration.name().to_string(),
+                ));
+            }
+            if migration.needs_read_connection() {
+                return Err(error::Error::ReadConnectionRequired(
+                    migration.name().to_string(),
+                ));
+            }
+        }
+        self.validate_local()?;
+
+        self.repo.invalidate()?;
+        let mut conn = conn.lock().await;
+        self.run_write_hooks(&self.before_all, &mut conn).await?;
+        let res = async {
+            let mut txn = conn.begin().await?;
+            let applied = self.apply_all_in_txn(&mut txn, true).await?;
+            if !self.dry_run {
+                txn.commit().await?;
+            }
+            Ok(applied)
+        }
+        .await;
+        let after = self.run_write_hooks(&self.after_all, &mut conn).await;
+        self.repo.invalidate()?;
+        res.and_then(|applied| after.map(|_| applied))
+    }
+
+    /// The body of `apply_all_in`, run between cache invalidations. With
+    /// `share_write` the migrations read through the write transaction
+    /// rather than a read only connection of their own.
+    async fn apply_all_in_txn(
+        &self,
+        txn: &mut Transaction<'_, DB>,
+        share_write: bool,
+    ) -> crate::error::Result<Vec<Cow<'static, str>>> {
+        self.set_role(txn).await?;
+        self.repo.init(txn).await?;
+        let lock_strategy = match self.lock_strategy {
+            LockStrategy::Advisory(key) => LockStrategy::AdvisoryXact(key),
+            strategy => strategy,
+        };
+        if lock_strategy != LockStrategy::None {
+            self.repo.lock(&lock_strategy, txn).await?;
+        }
+
+        let previously_applied = self.repo.get_all(txn).await?;
+        if !self.skip_validation {
+            self.validate_history(&previously_applied)?;
+        }
+        let applied_names = previously_applied
+            .iter()
+            .map(|x| self.normalize_name(&x.name))
+            .collect::<HashSet<_>>();
+        let migrations = self
+            .ordered_migrations()?
+            .into_iter()
+            .filter(|(_, x)| !applied_names.contains(&self.normalize_name(&x.name())))
+            .collect::<Vec<_>>();
+        self.run_in_txn(txn, migrations, share_write).await
+    }
+
+    /// Apply `migrations` in `txn`, keeping the UI up to date, and return
+    /// their names. With `share_write` they read through `txn` too. Their
+    /// tracking rows are written together with `insert_many` once they've
+    /// all run, since they're committed together anyway.
+    async fn run_in_txn(
+        &self,
+        txn: &mut Transaction<'_, DB>,
+        migrations: Vec<(i64, &dyn Migration<DB>)>,
+        share_write: bool,
+    ) -> crate::error::Result<Vec<Cow<'static, str>>> {
+        let ui = (*self.ui_factory)(&migrations);
+        let started = Instant::now();
+        let mut rows = Vec::with_capacity(migrations.len());
+        for (idx, &(ordering_key, migration)) in migrations.iter().enumerate() {
+            ui.start(idx, &Direction::Up);
+            let progress = ui.progress(idx);
+            let res = async {
+                let mut read = if share_write {
+                    None
+                } else {
+                    Some(self.acquire_read().await?)
+                };
+                let mut r = match &mut read {
+                    Some(read) => Some(self.begin_read_only(read).await?),
+                    None => None,
+                };
+                let mut ctx = MigrationCtx {
+                    read: r.as_deref_mut(),
+                    write: &mut **txn,
+                    direction: Direction::Up,
+                    dry_run: self.dry_run,
+                    progress: &*progress,
+                    table_name: self.repo.table_name(),
+                    migrator: self,
+                    migration: migration.name(),
+                };
+                self.up_in_session(migration, &mut ctx).await?;
+                self.repo.state_clear(&migration.name(), txn).await?;
+                self.notify(txn, migration).await
+            }
+            .await
+            .map_err(|e| e.in_migration(&migration.name(), Direction::Up));
+            if let Err(e) = res {
+                ui.fail(idx, &e);
+                return Err(e);
+            }
+            rows.push(self.completion_row(migration, ordering_key));
+            ui.finish(idx);
+        }
+        self.repo.insert_many(&rows, txn).await?;
+
+        if !migrations.is_empty() {
+            ui.complete(&RunSummary {
+                count: migrations.len(),
+                direction: Direction::Up,
+                elapsed: started.elapsed(),
+            });
+        }
+        Ok(migrations.iter().map(|(_, x)| x.name()).collect())
+    }
+
+    /// Apply the named migrations in a single transaction, so that either
+    /// all of them are applied or none are. Every migration has to be
+    /// `transactional`, and the group can't skip over a pending migration
+    /// that comes before one of its own. Named migrations that are already
+    /// applied are left alone. Nothing is retried or written to the
+    /// attempt log.
+    pub async fn apply_group(&self, names: &[&str]) -> crate::error::Result<()> {
+        for name in names {
+            let migration = self
+                .migration_named(name)
+                .ok_or_else(|| error::Error::NoSuchMigration(name.to_string()))?;
+            if !migration.transactional() {
+                return Err(error::Error::NonTransactionalMigration(name.to_string()));
+            }
+        }
+
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            let in_group = |x: &dyn Migration<DB>| {
+                names
+                    .iter()
+                    .any(|name| self.normalize_name(name) == self.normalize_name(&x.name()))
+            };
+            let unapplied = self.find_unapplied().await?;
+            let Some(last) = unapplied.iter().rposition(|(_, x)| in_group(*x)) else {
+                return Ok(());
+            };
+            if let Some((_, skipped)) = unapplied[..last].iter().find(|(_, x)| !in_group(*x)) {
+                return Err(error::Error::GroupSkipsMigration {
+                    name: unapplied[last].1.name().to_string(),
+                    skipped: skipped.name().to_string(),
+                });
+            }
+            let group = unapplied[..=last].to_vec();
+
+            let mut write = self.acquire("write").await?;
+            self.run_write_hooks(&self.before_all, &mut write).await?;
+            let res = async {
+                let mut w = self.begin_write(&mut write).await?;
+                let res = self.run_in_txn(&mut w, group, false).await;
+                match res {
+                    Ok(_) if !self.dry_run => w.commit().await.map_err(Into::into),
+                    res => res.map(drop),
+                }
+            }
+            .await;
+            let after = self.run_write_hooks(&self.after_all, &mut write).await;
+            let res = res.and(after);
+            if res.is_err() || self.dry_run {
+                // The rows recorded in the rolled back transaction are cached.
+                self.repo.invalidate()?;
+            }
+            res
+        })
+        .await
+    }
+
+    /// Revet all migrations that have been applied.
+    pub async fn revert_all(&self) -> crate::error::Result<RevertReport> {
+        let started = Instant::now();
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_for_revert().await?;
+
+            let to_revert = self.find_applied().await?;
+            self.revert_and_report(to_revert, started).await
+        })
+        .await
+    }
+
+    /// Revert `to_revert` in order, reporting what was reverted.
+    async fn revert_and_report(
+        &self,
+        to_revert: Vec<(i64, &dyn Migration<DB>)>,
+        started: Instant,
+    ) -> crate::error::Result<RevertReport> {
+        let reverted = to_revert.iter().map(|(_, x)| x.name()).collect();
+        self.apply_migrations(to_revert, Direction::Down).await?;
+        Ok(RevertReport {
+            reverted,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Return the database to how it was before promad: revert every
+    /// applied migration if `revert` is set, then drop the migrations table
+    /// along with the attempt log and migration state. Meant for local
+    /// development and test teardown. A dry run rolls back the drop along
+    /// with the reverts.
+    pub async fn reset(&self, revert: bool) -> crate::error::Result<()> {
+        if revert {
+            self.revert_all().await?;
+        }
+        let drop = async {
+            let mut conn = self.acquire("write").await?;
+            let mut txn = conn.begin().await?;
+            self.set_role(&mut txn).await?;
+            self.repo.drop_table(&mut txn).await?;
+            if !self.dry_run {
+                txn.commit().await?;
+            }
+            Ok(())
+        };
+        match self.lock_strategy {
+            // The table lock would be held on the table being dropped, and
+            // dropping it locks it exclusively anyway.
+            LockStrategy::TableLock => drop.await,
+            _ => self.locked(drop).await,
+        }
+    }
+
+    /// Record the migration named `name` as applied without running its
+    /// `up`, for when the change was already made by hand. Every migration
+    /// before it has to be applied already.
+    pub async fn apply_fake(&self, name: &str) -> crate::error::Result<()> {
+        let Some(migration) = self.migration_named(name) else {
+            return Err(error::Error::NoSuchMigration(name.to_string()));
+        };
+
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            let unapplied = self.find_unapplied().await?;
+            let is_target = |x: &dyn Migration<DB>| x.name() == migration.name();
+            let Some(&(ordering_key, _)) = unapplied.iter().find(|(_, x)| is_target(*x)) else {
+                return Err(error::Error::AlreadyApplied(name.to_string()));
+            };
+            if let Some((_, pending)) = unapplied.first().filter(|(_, x)| !is_target(*x)) {
+                return Err(error::Error::FakeOutOfOrder {
+                    name: name.to_string(),
+                    pending: pending.name().to_string(),
+                });
+            }
+
+            tracing::warn!("recording migration {name} as applied without running it");
+            let mut conn = self.acquire("write").await?;
+            let mut txn = self.begin_write(&mut conn).await?;
+            self.record_completion(&mut txn, migration, ordering_key)
+                .await?;
+            if !self.dry_run {
+                txn.commit().await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Update the stored checksums of applied migrations that are missing one
+    /// or that were computed with a different algorithm than the local one.
+    /// Checksums that disagree under the same algorithm are left alone since
+    /// they mean the migration was edited.
+    pub async fn restamp(&self) -> crate::error::Result<()> {
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_all().await?;
+
+            let mut conn = self.acquire("write").await?;
+            let mut txn = self.begin_write(&mut conn).await?;
+            for row in self.repo.get_all(&mut txn).await? {
+                let local = self.migration_named(&row.name).and_then(|x| x.checksum());
+                let applied = row.checksum.as_deref().and_then(Checksum::parse);
+                if let Some(local) = local {
+                    if !matches!(&applied, Some(x) if x.algo == local.algo) {
+                        self.repo
+                            .update_checksum(&row.name, Some(&local.to_string()), &mut txn)
+                            .await?;
+                    }
+                }
+            }
+            if self.dry_run {
+                // The new checksums are cached, but rolled back.
+                self.repo.invalidate()?;
+            } else {
+                txn.commit().await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replace the history of every applied migration up to and including
+    /// `up_to_name` with a single entry for a baseline migration, without
+    /// running anything against the schema.
+    ///
+    /// To rotate old migrations out:
+    /// 1. Write a baseline migration that creates the schema as it is after
+    ///    `up_to_name`, e.g. from `pg_dump --schema-only`.
+    /// 2. Remove the migrations it replaces and add the baseline first.
+    /// 3. Run `squash` against every existing database. Fresh databases run
+    ///    the baseline like any other migration.
+    ///
+    /// The migrations applied after `up_to_name` must follow the baseline
+    /// locally, in the same order. A dry run rolls the new history back.
+    pub async fn squash(&self, up_to_name: &str) -> crate::error::Result<()> {
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_name_uniqueness()?;
+
+            let mut conn = self.acquire("write").await?;
+            let mut txn = self.begin_write(&mut conn).await?;
+            let rows = self.repo.get_all(&mut txn).await?;
+            let squashed = rows
+                .iter()
+                .position(|x| self.normalize_name(&x.name) == self.normalize_name(up_to_name))
+                .ok_or_else(|| error::Error::NoSuchMigration(up_to_name.to_string()))?;
+            let ordered = self.ordered_migrations()?;
+            let Some(((baseline_key, baseline), rest)) = ordered.split_first() else {
+                return Err(error::Error::DeletedMigrations {
+                    db_migration_count: rows.len(),
+                    local_migration_count: 0,
+                });
+            };
+
+            // Everything after the squashed range has to line up with what
+            // follows the baseline, or the history would be inconsistent.
+            let kept = &rows[squashed + 1..];
+            if rest.len() < kept.len() {
+                return Err(error::Error::DeletedMigrations {
+                    db_migration_count: kept.len() + 1,
+                    local_migration_count: ordered.len(),
+                });
+            }
+            for (row, (_, local)) in kept.iter().zip(rest) {
+                if self.normalize_name(&row.name) != self.normalize_name(&local.name()) {
+                    return Err(error::Error::HistoryMigrationMismatch {
+                        remote_name: row.name.clone(),
+                        local_name: local.name().to_string(),
+                    });
+                }
+            }
+
+            let mut new_rows = vec![PromadRow {
+                name: baseline.name().to_string(),
+                ordering_key: *baseline_key,
+                created_at: self.now(),
+                checksum: baseline.checksum().map(|x| x.to_string()),
+                applied_by: Some(self.actor()),
+            }];
+            new_rows.extend(kept.iter().zip(rest).map(|(row, (key, _))| PromadRow {
+                ordering_key: *key,
+                ..row.clone()
+            }));
+            self.repo.clear(&mut txn).await?;
+            self.repo.insert_many(&new_rows, &mut txn).await?;
+            if self.dry_run {
+                // The squashed history is cached, but rolled back.
+                self.repo.invalidate()?;
+            } else {
+                txn.commit().await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// List all migration with data about whether they've been applied or not and when.
+    pub async fn list_migrations(&self) -> crate::error::Result<Vec<UiMigration>> {
+        self.validate_local()?;
+        self.init_sql().await?;
+
+        let mut read = self.acquire("read").await?;
+        let rows = self.repo.get_all(&mut read).await?;
+        self.validate_applied(&rows).await?;
+        let mut applied_migrations = rows
+            .into_iter()
+            .map(|row| (self.normalize_name(&row.name), row))
+            .collect::<HashMap<_, _>>();
+
+        Ok(self
+            .ordered_migrations()?
+            .into_iter()
+            .map(|(ordering_key, migration)| {
+                match applied_migrations.remove(&self.normalize_name(&migration.name())) {
+                    Some(row) => UiMigration {
+                        name: migration.name(),
+                        ordering_key: row.ordering_key,
+                        run_at: Some(row.created_at),
+                        applied_by: row.applied_by,
+                        description: migration.description().map(str::to_string),
+                    },
+                    None => UiMigration {
+                        name: migration.name(),
+                        ordering_key,
+                        run_at: None,
+                        applied_by: None,
+                        description: migration.description().map(str::to_string),
+                    },
+                }
+            })
+            .collect::<Vec<_>>())
+    }
+
+    /// Reverts all migrations up to and including the one with the given name.
+    pub async fn revert_to_inclusive(&self, name: &str) -> crate::error::Result<RevertReport> {
+        let started = Instant::now();
+        if self.migration_named(name).is_none() {
+            return Err(error::Error::NoSuchMigration(name.to_string()));
+        }
+
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_for_revert().await?;
+
+            let mut to_revert = Vec::new();
+
+            for (ordering_key, migration) in self.find_applied().await? {
+                to_revert.push((ordering_key, migration));
+                if self.normalize_name(&migration.name()) == self.normalize_name(name) {
+                    break;
+                }
+            }
+
+            self.revert_and_report(to_revert, started).await
+        })
+        .await
+    }
+
+    /// Revert every applied migration with an ordering key greater than
+    /// `key`, leaving the migration with that key applied.
+    pub async fn revert_after_key(&self, key: i64) -> crate::error::Result<()> {
+        self.check_ordering_key(key)?;
+
+        self.init_sql().await?;
+        self.locked(async {
+            self.validate_for_revert().await?;
+
+            let to_revert = self
+                .find_applied()
+                .await?
+                .into_iter()
+                .filter(|(ordering_key, _)| *ordering_key > key)
+                .collect();
+            self.apply_migrations(to_revert, Direction::Down).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    fn check_ordering_key(&self, key: i64) -> crate::error::Result<()> {
+        if !self.ordered_migrations()?.iter().any(|(x, _)| *x == key) {
+            return Err(error::Error::NoSuchOrderingKey(key));
+        }
         Ok(())
     }
 
-    /// Revet all migrations that have been applied.
-    pub async fn revert_all(&self) -> crate::error::Result<()> {
-        self.init_sql().await?;
-        self.validate_all().await?;
-
-        let mut conn = self.pool.acquire().await?;
-        let mut txn = conn.begin().await?;
-        let applied_migrations = self.repo.get_all(&mut txn).await?;
-
-        let to_revert = applied_migrations
+    /// The ordering key of the migration at `idx`: the key it declares, or
+    /// the one the ordering strategy gives it.
+    fn ordering_key(&self, idx: usize) -> i64 {
+        let migration = &self.migrations[idx];
+        migration
+            .ordering_key()
+            .unwrap_or_else(|| match self.ordering_strategy {
+                OrderingStrategy::Sequential => idx as i64,
+                OrderingStrategy::Timestamp => migration.timestamp().unwrap_or(idx as i64),
+            })
+    }
+
+    /// All local migrations the tag filter selects with their ordering keys,
+    /// in the order they're applied. Migrations come after everything they
+    /// depend on and are otherwise in ordering key order. Keys are worked
+    /// out over every migration, so migrators with different tag filters
+    /// sharing a table agree on them.
+    fn ordered_migrations(&self) -> crate::error::Result<Vec<(i64, &dyn Migration<DB>)>> {
+        let mut by_key = (0..self.migrations.len())
+            .map(|idx| (self.ordering_key(idx), &*self.migrations[idx]))
+            .collect::<Vec<_>>();
+        by_key.sort_by_key(|(key, _)| *key);
+
+        // Positions in `by_key` from here on.
+        let positions = by_key
+            .iter()
+            .enumerate()
+            .map(|(pos, (_, x))| (self.normalize_name(&x.name()), pos))
+            .collect::<HashMap<_, _>>();
+        let mut dependents = vec![vec![]; by_key.len()];
+        let mut unmet = vec![0; by_key.len()];
+        for (pos, (_, migration)) in by_key.iter().enumerate() {
+            for dependency in migration.depends_on() {
+                let dep_pos = positions
+                    .get(&self.normalize_name(dependency))
+                    .copied()
+                    // A dependency the tag filter leaves out would never be
+                    // applied by this migrator.
+                    .filter(|&x| !self.selected(*migration) || self.selected(by_key[x].1))
+                    .ok_or_else(|| error::Error::MissingDependency {
+                        name: migration.name().to_string(),
+                        dependency: dependency.to_string(),
+                    })?;
+                // Timestamp and declared keys can't be reassigned, so they
+                // have to agree.
+                let fixed = self.ordering_strategy == OrderingStrategy::Timestamp
+                    || migration.ordering_key().is_some()
+                    || by_key[dep_pos].1.ordering_key().is_some();
+                if fixed && dep_pos > pos {
+                    return Err(error::Error::DependencyOutOfOrder {
+                        name: migration.name().to_string(),
+                        dependency: dependency.to_string(),
+                    });
+                }
+                dependents[dep_pos].push(pos);
+                unmet[pos] += 1;
+            }
+        }
+
+        // Kahn's algorithm, always taking the earliest ready migration so
+        // that without dependencies nothing moves.
+        let mut ready = (0..by_key.len())
+            .filter(|&pos| unmet[pos] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(by_key.len());
+        while let Some(pos) = ready.pop_first() {
+            order.push(pos);
+            for &dependent in &dependents[pos] {
+                unmet[dependent] -= 1;
+                if unmet[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+        if let Some(pos) = (0..by_key.len()).find(|&pos| unmet[pos] > 0) {
+            return Err(error::Error::CyclicDependency(
+                by_key[pos].1.name().to_string(),
+            ));
+        }
+
+        Ok(order
+            .into_iter()
+            .enumerate()
+            .map(|(idx, pos)| match self.ordering_strategy {
+                OrderingStrategy::Sequential if by_key[pos].1.ordering_key().is_none() => {
+                    (idx as i64, by_key[pos].1)
+                }
+                _ => by_key[pos],
+            })
+            .filter(|(_, x)| self.selected(*x))
+            .collect())
+    }
+
+    /// The local migration named `name`, as `normalize_name` compares them.
+    fn migration_named(&self, name: &str) -> Option<&dyn Migration<DB>> {
+        let name = self.normalize_name(name);
+        self.migrations
+            .iter()
+            .find(|x| self.normalize_name(&x.name()) == name)
+            .map(|x| &**x)
+            .filter(|x| self.selected(*x))
+    }
+
+    /// The name `migration` was recorded under, which can differ from its
+    /// own in the ways `normalize_name` ignores.
+    async fn recorded_name(
+        &self,
+        conn: &mut DB::Connection,
+        migration: &dyn Migration<DB>,
+    ) -> crate::error::Result<String> {
+        let name = self.normalize_name(&migration.name());
+        Ok(self
+            .repo
+            .get_all(conn)
+            .await?
+            .into_iter()
+            .map(|x| x.name)
+            .find(|x| self.normalize_name(x) == name)
+            .unwrap_or_else(|| migration.name().to_string()))
+    }
+
+    /// The migrations the tag filter, if any, lets through.
+    fn selected_migrations(&self) -> impl Iterator<Item = &dyn Migration<DB>> {
+        self.migrations
             .iter()
-            .rev()
-            .map(|x| (x.ordering_key, &*self.migrations[x.ordering_key as usize]))
-            .collect::<Vec<_>>();
-
-        self.apply_migrations(to_revert, Direction::Down).await?;
-        Ok(())
+            .map(|x| &**x)
+            .filter(|x| self.selected(*x))
     }
 
-    /// List all migration with data about whether they've been applied or not and when.
-    pub async fn list_migrations(&self) -> crate::error::Result<Vec<UiMigration>> {
-        self.init_sql().await?;
-        self.validate_all().await?;
-
-        let mut read = self.pool.acquire().await?;
-        let applied_migrations = self.repo.get_all(&mut read).await?;
+    /// Whether the tag filter, if any, lets `migration` through.
+    fn selected(&self, migration: &dyn Migration<DB>) -> bool {
+        let Some(tag) = &self.tag_filter else {
+            return true;
+        };
+        let tags = migration.tags();
+        tags.is_empty() || tags.contains(&tag.as_str())
+    }
 
-        Ok(self
-            .migrations
+    /// Whether an applied row is one this migrator considers. Rows of
+    /// migrations the tag filter leaves out belong to someone else, while
+    /// rows without a local migration are still ours to complain about.
+    fn selected_row(&self, row: &PromadRow) -> bool {
+        let name = self.normalize_name(&row.name);
+        self.migrations
             .iter()
-            .map(|x| Some(x))
-            .zip(
-                applied_migrations
-                    .into_iter()
-                    .map(Some)
-                    .chain(std::iter::repeat(None)),
-            )
-            .filter_map(|(x, y)| match (x, y) {
-                (Some(x), Some(y)) => Some(UiMigration {
-                    name: x.name().into(),
-                    run_at: Some(y.created_at),
-                }),
-                (Some(x), None) => Some(UiMigration {
-                    name: x.name().into(),
-                    run_at: None,
-                }),
-                _ => None,
-            })
-            .collect::<Vec<_>>())
+            .find(|x| self.normalize_name(&x.name()) == name)
+            .is_none_or(|x| self.selected(&**x))
     }
 
-    /// Reverts all migrations up to and including the one with the given name.
-    pub async fn revert_to_inclusive(&self, name: &str) -> crate::error::Result<()> {
-        self.init_sql().await?;
-        self.validate_all().await?;
-        if !self.migrations.iter().map(|x| x.name()).any(|x| x == name) {
-            return Err(error::Error::NoSuchMigration(name.to_string()));
+    /// Check up front that the database can be migrated: it's reachable,
+    /// the tracking table can be created and written to, tables can be
+    /// created and dropped, and the migration lock isn't held. Nothing is
+    /// left behind in the database.
+    pub async fn preflight(&self) -> crate::error::Result<()> {
+        for check in PreflightCheck::ALL {
+            self.preflight_check(check)
+                .await
+                .map_err(|e| error::Error::PreflightFailed {
+                    check,
+                    source: Box::new(e),
+                })?;
         }
+        Ok(())
+    }
 
-        let mut conn = self.pool.acquire().await?;
-        let mut txn = conn.begin().await?;
-        let applied_migrations = self.repo.get_all(&mut txn).await?;
-
-        let mut to_revert = Vec::new();
-
-        for migration in applied_migrations.iter().rev() {
-            to_revert.push((
-                migration.ordering_key,
-                &*self.migrations[migration.ordering_key as usize],
-            ));
-            if migration.name == name {
-                break;
+    /// Run a single preflight check. See `preflight`.
+    pub async fn preflight_check(&self, check: PreflightCheck) -> crate::error::Result<()> {
+        let mut conn = self.acquire("preflight").await?;
+        match check {
+            PreflightCheck::Connection => {
+                conn.ping().await?;
+            }
+            PreflightCheck::TrackingTable | PreflightCheck::WritePermission => {
+                // Everything happens in a transaction that's rolled back, and
+                // the cache is dropped so none of it is remembered either.
+                let mut txn = conn.begin().await?;
+                let res = async {
+                    self.set_role(&mut txn).await?;
+                    self.repo.init(&mut txn).await?;
+                    self.repo.get_all(&mut txn).await?;
+                    if check == PreflightCheck::WritePermission {
+                        let row = PromadRow {
+                            name: "_promad_preflight".to_string(),
+                            ordering_key: -1,
+                            created_at: self.now(),
+                            checksum: None,
+                            applied_by: None,
+                        };
+                        self.repo.insert(&row, &mut txn).await?;
+                        self.repo.delete("_promad_preflight", &mut txn).await?;
+                    }
+                    Ok::<_, error::Error>(())
+                }
+                .await;
+                self.repo.invalidate()?;
+                txn.rollback().await?;
+                res?;
+            }
+            PreflightCheck::DdlPermission => {
+                let mut txn = conn.begin().await?;
+                self.set_role(&mut txn).await?;
+                self.repo.check_ddl(&mut txn).await?;
+                txn.rollback().await?;
+            }
+            PreflightCheck::LockAvailable => {
+                let mut txn = conn.begin().await?;
+                let available = self.repo.try_lock(&self.lock_strategy, &mut txn).await?;
+                txn.rollback().await?;
+                if !available {
+                    return Err(error::Error::LockUnavailable);
+                }
             }
         }
-
-        self.apply_migrations(to_revert, Direction::Down).await?;
         Ok(())
     }
 
+    /// Run `op` while holding the migration lock so that concurrent migrators
+    /// don't plan and apply the same migrations. The lock is released even
+    /// if `op` fails.
+    async fn locked<T>(
+        &self,
+        op: impl Future<Output = crate::error::Result<T>>,
+    ) -> crate::error::Result<T> {
+        self.check_dry_run()?;
+        let guard = match self.lock_strategy {
+            LockStrategy::None => LockGuard::None,
+            LockStrategy::Advisory(_) => {
+                let mut conn = self.acquire("lock").await?;
+                self.repo.lock(&self.lock_strategy, &mut conn).await?;
+                LockGuard::Session(SessionLock(Some(conn)))
+            }
+            LockStrategy::AdvisoryXact(_) | LockStrategy::TableLock => {
+                let mut txn = self.acquire_within("lock", self.pool()?.begin()).await?;
+                self.repo.lock(&self.lock_strategy, &mut txn).await?;
+                LockGuard::Transaction(txn)
+            }
+        };
+
+        let res = op.await;
+
+        let released = match guard {
+            LockGuard::None => Ok(()),
+            LockGuard::Session(lock) => {
+                let mut conn = lock.into_inner();
+                let released = self.repo.unlock(&self.lock_strategy, &mut conn).await;
+                if released.is_err() {
+                    // Closing the session releases the lock too.
+                    let _ = conn.detach().close().await;
+                }
+                released
+            }
+            LockGuard::Transaction(txn) => txn.commit().await.map_err(Into::into),
+        };
+
+        let value = res?;
+        released?;
+        Ok(value)
+    }
+
     /// Runs the database specific SQL to initialize the tracking table.
+    /// Every operation starts here, so this is also where the repo cache is
+    /// invalidated so we never act on state written by another process.
     async fn init_sql(&self) -> crate::error::Result<()> {
-        let mut write = self.pool.acquire().await?;
+        self.repo.invalidate()?;
+        let mut attempt = 1;
+        loop {
+            match self.try_init_sql().await {
+                // Processes starting together all create the tables at once.
+                // The one that loses has its transaction rolled back, but by
+                // then the tables exist, so trying again finds them.
+                Err(e) if e.is_concurrent_ddl() && attempt < INIT_ATTEMPTS => {
+                    tracing::debug!("creating the migrations table raced another process: {e}");
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    async fn try_init_sql(&self) -> crate::error::Result<()> {
+        let mut write = self.acquire("write").await?;
         let mut txn = write.begin().await?;
+        self.set_role(&mut txn).await?;
         self.repo.init(&mut txn).await?;
         txn.commit().await?;
         Ok(())
@@ -429,8 +3234,115 @@ impl<DB: Database> Migrator<DB> {
 
     /// Check that the migrations given pass all validation rule.
     async fn validate_all(&self) -> crate::error::Result<()> {
+        self.validate_local()?;
+        self.validate_remote().await
+    }
+
+    /// Validate the migrations in the database against the local ones.
+    async fn validate_remote(&self) -> crate::error::Result<()> {
+        let mut read = self.acquire("read").await?;
+        let previously_applied = self.repo.get_all(&mut read).await?;
+        self.validate_applied(&previously_applied).await
+    }
+
+    /// `validate_remote` with the rows of the tracking table already read,
+    /// for callers that need them anyway.
+    async fn validate_applied(&self, previously_applied: &[PromadRow]) -> crate::error::Result<()> {
+        if self.skip_validation {
+            tracing::warn!("skipping validation of the migration history");
+            return Ok(());
+        }
+        self.validate_name_lengths().await?;
+        self.validate_history(previously_applied)
+    }
+
+    /// `validate_all`, leaving out applied migrations that don't exist
+    /// locally with `with_skip_missing_on_revert`.
+    async fn validate_for_revert(&self) -> crate::error::Result<()> {
+        self.validate_local()?;
+        let mut read = self.acquire("read").await?;
+        let mut previously_applied = self.repo.get_all(&mut read).await?;
+        if self.skip_missing_on_revert {
+            previously_applied.retain(|x| self.migration_named(&x.name).is_some());
+        }
+        self.validate_applied(&previously_applied).await
+    }
+
+    /// Validate that every migration name fits in the table's name column.
+    async fn validate_name_lengths(&self) -> crate::error::Result<()> {
+        let mut conn = self.acquire("read").await?;
+        let Some(max) = self.repo.max_name_length(&mut conn).await? else {
+            return Ok(());
+        };
+        match self
+            .migrations
+            .iter()
+            .find(|x| x.name().chars().count() > max)
+        {
+            Some(migration) => Err(error::Error::MigrationNameTooLong {
+                name: migration.name().to_string(),
+                max,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the local migrations without touching the database: names are
+    /// unique, declared ordering keys agree with the ordering strategy and
+    /// leave no gaps with `with_contiguous_ordering_keys` and, with
+    /// `with_numbered_names`, names are numbered. Meant for a plain
+    /// unit test of a migration list. Adding migrations and every operation
+    /// run these checks too, adding them with whatever configuration has
+    /// been set so far.
+    pub fn validate_local(&self) -> crate::error::Result<()> {
         self.validate_name_uniqueness()?;
-        self.validate_db_against_local().await?;
+        self.validate_ordering_keys()?;
+        self.validate_contiguous_ordering_keys()?;
+        self.validate_numbered_names()
+    }
+
+    /// Validate that the ordering keys have no gaps, if
+    /// `with_contiguous_ordering_keys` asks for it.
+    fn validate_contiguous_ordering_keys(&self) -> crate::error::Result<()> {
+        if !self.contiguous_ordering_keys {
+            return Ok(());
+        }
+        let mut keys = (0..self.migrations.len())
+            .map(|idx| (self.ordering_key(idx), idx))
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        for pair in keys.windows(2) {
+            let ((previous, _), (key, idx)) = (pair[0], pair[1]);
+            let expected = previous.saturating_add(1);
+            if key != expected {
+                return Err(error::Error::OrderingKeyGap {
+                    name: self.migrations[idx].name().to_string(),
+                    key,
+                    expected,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate that migration names have the prefix `with_numbered_names`
+    /// asks for.
+    fn validate_numbered_names(&self) -> crate::error::Result<()> {
+        let Some(digits) = self.numbered_names else {
+            return Ok(());
+        };
+        for migration in &self.migrations {
+            let name = migration.name();
+            let numbered = name.len() > digits
+                && name.as_bytes()[..digits].iter().all(u8::is_ascii_digit)
+                && name.as_bytes()[digits] == b'_';
+            if !numbered {
+                return Err(error::Error::InvalidMigrationName {
+                    name: name.to_string(),
+                    reason: format!("name doesn't start with {digits} digits and an underscore"),
+                });
+            }
+        }
         Ok(())
     }
 
@@ -438,44 +3350,211 @@ impl<DB: Database> Migrator<DB> {
     fn validate_name_uniqueness(&self) -> crate::error::Result<()> {
         let mut names = std::collections::HashSet::new();
         for migration in &self.migrations {
-            if names.contains(&migration.name()) {
+            if !names.insert(self.normalize_name(&migration.name())) {
                 return Err(error::Error::DuplicateMigrationName(
                     migration.name().to_string(),
                 ));
             }
-            names.insert(migration.name());
         }
         Ok(())
     }
 
-    /// Validate that the migrations in the database match the ones in the local directory.
-    async fn validate_db_against_local<'a>(&self) -> crate::error::Result<()> {
-        let mut read = self.pool.acquire().await?;
-        let previously_applied = self
-            .repo
-            .get_all(&mut read)
-            .await?
-            .into_iter()
+    /// Validate that declared ordering keys are unique and, with
+    /// `OrderingStrategy::Sequential`, that keys increase down the list.
+    fn validate_ordering_keys(&self) -> crate::error::Result<()> {
+        let keys = (0..self.migrations.len())
+            .map(|idx| self.ordering_key(idx))
+            .collect::<Vec<_>>();
+        for (idx, migration) in self.migrations.iter().enumerate() {
+            if migration.ordering_key().is_none() {
+                continue;
+            }
+            if let Some(other) = (0..keys.len()).find(|&x| x != idx && keys[x] == keys[idx]) {
+                return Err(error::Error::DuplicateOrderingKey {
+                    key: keys[idx],
+                    first: self.migrations[idx.min(other)].name().to_string(),
+                    second: self.migrations[idx.max(other)].name().to_string(),
+                });
+            }
+        }
+        if self.ordering_strategy == OrderingStrategy::Sequential {
+            if let Some(idx) = (1..keys.len()).find(|&x| keys[x] <= keys[x - 1]) {
+                return Err(error::Error::OrderingKeyOutOfOrder {
+                    name: self.migrations[idx].name().to_string(),
+                    key: keys[idx],
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check names about to be added against each other and the migrations
+    /// already added.
+    fn validate_new_names<'a>(
+        &self,
+        new_names: impl Iterator<Item = &'a str>,
+    ) -> crate::error::Result<()> {
+        let mut names = self
+            .migrations
+            .iter()
+            .map(|x| self.normalize_name(&x.name()))
+            .collect::<HashSet<_>>();
+        for name in new_names {
+            validate_name(name)?;
+            if !names.insert(self.normalize_name(name)) {
+                return Err(error::Error::DuplicateMigrationName(name.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The form of a name that's compared when checking for duplicates.
+    fn normalize_name(&self, name: &str) -> String {
+        let name = name.trim();
+        if self.case_insensitive_names {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Warn about clock skew in the applied history. Only a warning since
+    /// the ordering key, not the time, is what orders migrations. Every
+    /// operation reads the history again, so after the first warning it's
+    /// only logged at debug level.
+    fn warn_on_clock_skew(&self, rows: &[PromadRow]) {
+        let Some((before, after)) = clock_skew(rows) else {
+            return;
+        };
+        let message = format!(
+            "migration {} was recorded at {}, earlier than {} which precedes it ({}). \
+             The clocks of the machines applying migrations may be skewed, \
+             see Migrator::with_server_side_timestamps",
+            after.name, after.created_at, before.name, before.created_at
+        );
+        if self.clock_skew_warned.swap(true, Ordering::Relaxed) {
+            tracing::debug!("{message}");
+        } else {
+            tracing::warn!("{message}");
+        }
+    }
+
+    /// Check the applied migrations against the local ones.
+    fn validate_history(&self, previously_applied: &[PromadRow]) -> crate::error::Result<()> {
+        self.warn_on_clock_skew(previously_applied);
+        let previously_applied = previously_applied
+            .iter()
+            .filter(|row| self.selected_row(row))
             .collect::<Vec<_>>();
-        if self.migrations.len() < previously_applied.len() {
+        let local_count = self.selected_migrations().count();
+        if local_count < previously_applied.len() {
             return Err(error::Error::DeletedMigrations {
                 db_migration_count: previously_applied.len(),
-                local_migration_count: self.migrations.len(),
+                local_migration_count: local_count,
             });
         }
 
-        for (i, row) in previously_applied.iter().enumerate() {
-            let local_migration = &*self.migrations[i];
-            if local_migration.name() != row.name {
+        let mut local_migrations = self.ordered_migrations()?;
+        // A parallel run that failed part way can have applied migrations
+        // that come after ones it didn't, so only the order of the applied
+        // migrations is checked.
+        if self.parallelism > 1 {
+            let applied = previously_applied
+                .iter()
+                .map(|x| self.normalize_name(&x.name))
+                .collect::<HashSet<_>>();
+            local_migrations.retain(|(_, x)| applied.contains(&self.normalize_name(&x.name())));
+            if local_migrations.len() < previously_applied.len() {
+                return Err(error::Error::DeletedMigrations {
+                    db_migration_count: previously_applied.len(),
+                    local_migration_count: local_migrations.len(),
+                });
+            }
+        }
+        for (row, (_, local_migration)) in previously_applied.iter().zip(local_migrations) {
+            if self.normalize_name(&local_migration.name()) != self.normalize_name(&row.name) {
                 return Err(error::Error::HistoryMigrationMismatch {
                     remote_name: row.name.clone(),
                     local_name: local_migration.name().to_string(),
                 });
             }
+
+            // A checksum from a different algorithm only needs restamping.
+            let applied = row.checksum.as_deref().and_then(Checksum::parse);
+            if let (Some(applied), Some(local)) = (applied, local_migration.checksum()) {
+                if applied.algo != local.algo {
+                    tracing::warn!(
+                        "the checksum of migration {} was computed with {} instead of {}, so edits to it can't be detected; run restamp to update it",
+                        row.name,
+                        applied.algo.as_str(),
+                        local.algo.as_str()
+                    );
+                } else if applied.bytes != local.bytes {
+                    return Err(error::Error::ChecksumMismatch {
+                        name: row.name.clone(),
+                        applied: applied.to_string(),
+                        local: local.to_string(),
+                    });
+                }
+            }
         }
         Ok(())
     }
 
+    /// Open an entry in the attempt log, if it's on. Dry runs aren't logged.
+    async fn start_attempt(
+        &self,
+        migration: &dyn Migration<DB>,
+        direction: Direction,
+    ) -> crate::error::Result<Option<i64>> {
+        if self.dry_run {
+            return Ok(None);
+        }
+        let mut conn = self.acquire("attempt log").await?;
+        self.repo
+            .start_attempt(&migration.name(), direction, &mut conn)
+            .await
+    }
+
+    /// Close an entry in the attempt log with the error the run ended in.
+    async fn finish_attempt(
+        &self,
+        id: i64,
+        error: Option<&error::Error>,
+    ) -> crate::error::Result<()> {
+        let mut conn = self.acquire("attempt log").await?;
+        let error = error.map(|e| e.to_string());
+        self.repo
+            .finish_attempt(id, error.as_deref(), &mut conn)
+            .await
+    }
+
+    /// The time to record a migration as applied at.
+    fn now(&self) -> chrono::DateTime<Utc> {
+        self.timestamp_precision.truncate(self.clock.now())
+    }
+
+    /// Who to record as having applied migrations.
+    fn actor(&self) -> String {
+        self.actor
+            .clone()
+            .or_else(|| std::env::var("PROMAD_ACTOR").ok())
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// The tracking row recording that the migration has been applied.
+    fn completion_row(&self, migration: &dyn Migration<DB>, ordering_key: i64) -> PromadRow {
+        PromadRow {
+            name: migration.name().to_string(),
+            ordering_key,
+            created_at: self.now(),
+            checksum: migration.checksum().map(|x| x.to_string()),
+            applied_by: Some(self.actor()),
+        }
+    }
+
     /// Write to the tracking table that the migration has been applied.
     async fn record_completion(
         &self,
@@ -483,51 +3562,463 @@ impl<DB: Database> Migrator<DB> {
         migration: &dyn Migration<DB>,
         ordering_key: i64,
     ) -> crate::error::Result<()> {
-        self.repo
-            .insert(
-                &PromadRow {
-                    name: migration.name().to_string(),
-                    ordering_key,
-                    created_at: Utc::now(),
-                },
-                write,
-            )
+        let inserted = self
+            .repo
+            .insert(&self.completion_row(migration, ordering_key), write)
             .await?;
+        self.repo.state_clear(&migration.name(), write).await?;
+        self.notify(write, migration).await?;
+        if !inserted {
+            // Another process applied it first, which only an idempotent
+            // `up` survives intact.
+            tracing::warn!(
+                "migration {} was already recorded by another process",
+                migration.name()
+            );
+        }
         Ok(())
     }
 
+    /// Run a single migration in the given direction, retrying transient
+    /// connection failures according to the retry policy.
+    async fn run_one(
+        &self,
+        migration: &dyn Migration<DB>,
+        ordering_key: i64,
+        direction: Direction,
+        progress: &dyn ProgressReporter,
+    ) -> crate::error::Result<()> {
+        let mut attempt = 1;
+        loop {
+            // Set once the commit has been issued. If it fails we can't know
+            // whether the migration was applied, so it must not be retried.
+            let mut committing = false;
+            let res = match direction {
+                Direction::Up => {
+                    self.apply_one_internal(migration, ordering_key, progress, &mut committing)
+                        .await
+                }
+                Direction::Down => {
+                    self.revert_one_internal(migration, progress, &mut committing)
+                        .await
+                }
+            };
+            let delay = self.retry.delay_after(attempt);
+            match (res, delay) {
+                (Err(e), Some(delay)) if !committing && e.is_transient() => {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                (res, _) => return res.map_err(|e| e.in_migration(&migration.name(), direction)),
+            }
+        }
+    }
+
     /// Helper for applying a single migration in a transaction.
     async fn apply_one_internal(
         &self,
         migration: &dyn Migration<DB>,
         ordering_key: i64,
+        progress: &dyn ProgressReporter,
+        committing: &mut bool,
     ) -> crate::error::Result<()> {
-        let mut read = self.pool.acquire().await?;
-        let mut write = self.pool.acquire().await?;
-
-        let mut r = read.begin().await?;
-        self.repo.set_read_only(&mut r).await?;
-        let mut w = write.begin().await?;
-        migration.up(&mut r, &mut *w).await?;
-        self.record_completion(&mut *w, migration, ordering_key)
+        let mut read = self.acquire_read().await?;
+        let mut write = self.acquire("write").await?;
+        self.run_write_hooks(&self.before_all, &mut write).await?;
+        let res: crate::error::Result<()> = async {
+            let session_id = self.watched_session(&mut write).await?;
+            let mut r = self.begin_read_only(&mut read).await?;
+            let mut w = self.begin_write(&mut write).await?;
+            let mut ctx = MigrationCtx {
+                read: Some(&mut *r),
+                write: &mut *w,
+                direction: Direction::Up,
+                dry_run: self.dry_run,
+                progress,
+                table_name: self.repo.table_name(),
+                migrator: self,
+                migration: migration.name(),
+            };
+            self.watch(
+                migration,
+                session_id,
+                self.up_in_session(migration, &mut ctx),
+            )
             .await?;
-        w.commit().await?;
+            self.record_completion(&mut *w, migration, ordering_key)
+                .await?;
+            if !self.dry_run {
+                *committing = true;
+                w.commit().await?;
+            }
+
+            Ok(())
+        }
+        .await;
+        // The settings mustn't outlive the run on the pooled connection.
+        // The migration's error matters more than the hook's.
+        let after = self.run_write_hooks(&self.after_all, &mut write).await;
+        res.and(after)
+    }
 
+    /// Notify the channel from `with_notify_channel` about `migration`.
+    async fn notify(
+        &self,
+        write: &mut <DB as Database>::Connection,
+        migration: &dyn Migration<DB>,
+    ) -> crate::error::Result<()> {
+        if let Some(channel) = &self.notify_channel {
+            self.repo.notify(channel, &migration.name(), write).await?;
+        }
         Ok(())
     }
 
-    // Helper for reverting a single migration in a transaction.
-    async fn revert_one_internal(&self, migration: &dyn Migration<DB>) -> crate::error::Result<()> {
-        let mut read = self.pool.acquire().await?;
-        let mut write = self.pool.acquire().await?;
+    /// Runs `up` between the migration's session setup and teardown.
+    /// Teardown runs after a failed `up` only outside a transaction, and
+    /// then its own error is dropped in favour of the one from `up`.
+    async fn up_in_session(
+        &self,
+        migration: &dyn Migration<DB>,
+        ctx: &mut MigrationCtx<'_, DB>,
+    ) -> crate::error::Result<()> {
+        for sql in migration.session_setup() {
+            self.repo.execute(sql, ctx.write()).await?;
+        }
+        let res = migration.up(ctx).await;
+        if res.is_err() && migration.transactional() {
+            return res;
+        }
+        let mut teardown = Ok(());
+        for sql in migration.session_teardown() {
+            teardown = self.repo.execute(sql, ctx.write()).await;
+            if teardown.is_err() {
+                break;
+            }
+        }
+        res.and(teardown)
+    }
+
+    /// Get a connection from the pool for `phase`, giving up after the
+    /// acquire timeout.
+    async fn acquire(&self, phase: &'static str) -> crate::error::Result<PoolConnection<DB>> {
+        self.acquire_within(phase, self.pool()?.acquire()).await
+    }
+
+    /// Get the connection a migration reads through, from the read pool if
+    /// there is one.
+    async fn acquire_read(&self) -> crate::error::Result<PoolConnection<DB>> {
+        match &self.read_pool {
+            Some(pool) => self.acquire_within("read", pool.acquire()).await,
+            None => self.acquire("read").await,
+        }
+    }
+
+    /// See `MigrationCtx::state_get`.
+    async fn state_get(&self, migration: &str, key: &str) -> crate::error::Result<Option<String>> {
+        let mut conn = self.acquire_state(migration).await?;
+        let mut txn = conn.begin().await?;
+        self.set_role(&mut txn).await?;
+        let value = self.repo.state_get(migration, key, &mut txn).await?;
+        txn.commit().await?;
+        Ok(value)
+    }
+
+    /// See `MigrationCtx::state_set`.
+    async fn state_set(&self, migration: &str, key: &str, value: &str) -> crate::error::Result<()> {
+        let mut conn = self.acquire_state(migration).await?;
+        let mut txn = conn.begin().await?;
+        self.set_role(&mut txn).await?;
+        self.repo.state_set(migration, key, value, &mut txn).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Get the connection `migration`'s saved state is read and written on.
+    async fn acquire_state(&self, migration: &str) -> crate::error::Result<PoolConnection<DB>> {
+        if self.pool.is_none() {
+            return Err(error::Error::StateRequiresPool(migration.to_string()));
+        }
+        self.acquire("state").await
+    }
+
+    /// Fail a dry run up front if the repo would keep what it records.
+    fn check_dry_run(&self) -> crate::error::Result<()> {
+        if self.dry_run && !self.repo.is_transactional() {
+            return Err(error::Error::DryRunUnsupported);
+        }
+        Ok(())
+    }
+
+    /// The pool, which everything but applying on a single connection needs.
+    fn pool(&self) -> crate::error::Result<&Pool<DB>> {
+        self.pool.as_ref().ok_or(error::Error::PoolRequired)
+    }
+
+    /// Wait for a connection being acquired for `phase` for at most the
+    /// acquire timeout, if there is one.
+    async fn acquire_within<T>(
+        &self,
+        phase: &'static str,
+        acquiring: impl Future<Output = sqlx::Result<T>>,
+    ) -> crate::error::Result<T> {
+        let Some(timeout) = self.acquire_timeout else {
+            return Ok(acquiring.await?);
+        };
+        match tokio::time::timeout(timeout, acquiring).await {
+            Ok(res) => Ok(res?),
+            Err(_) => Err(error::Error::AcquireTimeout { phase, timeout }),
+        }
+    }
 
+    /// Begin the transaction migrations read through. Nothing may run in it
+    /// before it's made read only, or Postgres ignores the request.
+    async fn begin_read_only<'c>(
+        &self,
+        read: &'c mut PoolConnection<DB>,
+    ) -> crate::error::Result<Transaction<'c, DB>> {
         let mut r = read.begin().await?;
+        if let Some(level) = self.isolation {
+            self.repo.set_isolation(level, &mut r).await?;
+        }
         self.repo.set_read_only(&mut r).await?;
+        self.set_role(&mut r).await?;
+        Ok(r)
+    }
+
+    /// Begin the write transaction of a migration.
+    async fn begin_write<'c>(
+        &self,
+        write: &'c mut PoolConnection<DB>,
+    ) -> crate::error::Result<Transaction<'c, DB>> {
         let mut w = write.begin().await?;
-        migration.down(&mut r, &mut *w).await?;
-        self.repo.delete(migration.name().into(), &mut *w).await?;
-        w.commit().await?;
+        if let Some(level) = self.isolation {
+            self.repo.set_isolation(level, &mut w).await?;
+        }
+        self.set_role(&mut w).await?;
+        Ok(w)
+    }
 
+    /// Switch the current transaction to the role from `with_role`, if any.
+    async fn set_role(&self, conn: &mut <DB as Database>::Connection) -> crate::error::Result<()> {
+        if let Some(role) = &self.role {
+            validate_role(role)?;
+            self.repo.set_role(role, conn).await?;
+        }
         Ok(())
     }
+
+    /// The session of the write connection, if the lock watchdog is on.
+    async fn watched_session(
+        &self,
+        write: &mut PoolConnection<DB>,
+    ) -> crate::error::Result<Option<i64>> {
+        match self.lock_watchdog {
+            Some(_) => self.repo.session_id(write).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Run a migration's `up` or `down`, reporting what it's blocked on
+    /// each time it exceeds the lock watchdog threshold.
+    async fn watch(
+        &self,
+        migration: &dyn Migration<DB>,
+        session_id: Option<i64>,
+        work: impl Future<Output = crate::error::Result<()>>,
+    ) -> crate::error::Result<()> {
+        let (Some(threshold), Some(session_id)) = (self.lock_watchdog, session_id) else {
+            return work.await;
+        };
+        let started = Instant::now();
+        // Probing alongside the migration rather than between polls of it,
+        // so it keeps making progress while the watchdog waits for a
+        // connection or for the database.
+        let probe = async {
+            loop {
+                tokio::time::sleep(threshold).await;
+                // The watchdog failing shouldn't fail the migration.
+                let blockers = match self.acquire("watchdog").await {
+                    Ok(mut conn) => self
+                        .repo
+                        .blocking_sessions(session_id, &mut conn)
+                        .await
+                        .unwrap_or_default(),
+                    Err(_) => vec![],
+                };
+                for blocker in blockers {
+                    tracing::warn!(
+                        "migration {} blocked by PID {} for {}s",
+                        migration.name(),
+                        blocker,
+                        started.elapsed().as_secs()
+                    );
+                }
+            }
+        };
+        let (work, probe) = (std::pin::pin!(work), std::pin::pin!(probe));
+        match futures_util::future::select(work, probe).await {
+            futures_util::future::Either::Left((res, _)) => res,
+            futures_util::future::Either::Right(_) => unreachable!("the probe never finishes"),
+        }
+    }
+
+    // Helper for reverting a single migration in a transaction.
+    async fn revert_one_internal(
+        &self,
+        migration: &dyn Migration<DB>,
+        progress: &dyn ProgressReporter,
+        committing: &mut bool,
+    ) -> crate::error::Result<()> {
+        let mut read = self.acquire_read().await?;
+        let mut write = self.acquire("write").await?;
+        self.run_write_hooks(&self.before_all, &mut write).await?;
+        let res: crate::error::Result<()> = async {
+            let session_id = self.watched_session(&mut write).await?;
+            let mut r = self.begin_read_only(&mut read).await?;
+            let mut w = self.begin_write(&mut write).await?;
+            let before = if self.strict_revert {
+                Some(self.repo.schema_snapshot(&mut w).await?)
+            } else {
+                None
+            };
+            let mut ctx = MigrationCtx {
+                read: Some(&mut *r),
+                write: &mut *w,
+                direction: Direction::Down,
+                dry_run: self.dry_run,
+                progress,
+                table_name: self.repo.table_name(),
+                migrator: self,
+                migration: migration.name(),
+            };
+            let started = Instant::now();
+            self.watch(migration, session_id, migration.down(&mut ctx))
+                .await?;
+            let elapsed = started.elapsed();
+            if before.is_none() && migration.reversible() && elapsed < self.no_op_down {
+                tracing::warn!(
+                    "reverting migration {} finished in {:?}, too fast to have run any SQL, \
+                     so its down may be a no-op. Mark it irreversible if that's intended, \
+                     or catch it with Migrator::with_strict_revert",
+                    migration.name(),
+                    elapsed
+                );
+            }
+            if let Some(before) = before {
+                // Nothing else has run in the transaction yet, so any rows
+                // changed were changed by `down`.
+                if self.repo.rows_changed(&mut w).await? == 0
+                    && self.repo.schema_snapshot(&mut w).await? == before
+                {
+                    return Err(error::Error::EmptyRevert(migration.name().to_string()));
+                }
+            }
+            let recorded = self.recorded_name(&mut w, migration).await?;
+            self.repo.delete(&recorded, &mut *w).await?;
+            self.repo.state_clear(&migration.name(), &mut *w).await?;
+            self.notify(&mut w, migration).await?;
+            if !self.dry_run {
+                *committing = true;
+                w.commit().await?;
+            }
+
+            Ok(())
+        }
+        .await;
+        // The settings mustn't outlive the run on the pooled connection.
+        // The migration's error matters more than the hook's.
+        let after = self.run_write_hooks(&self.after_all, &mut write).await;
+        res.and(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let retry = RetryPolicy {
+            max_attempts: 4,
+            backoff: Duration::from_millis(100),
+        };
+        assert_eq!(retry.delay_after(1), Some(Duration::from_millis(100)));
+        assert_eq!(retry.delay_after(2), Some(Duration::from_millis(200)));
+        assert_eq!(retry.delay_after(3), Some(Duration::from_millis(400)));
+        assert_eq!(retry.delay_after(4), None);
+
+        // The default makes a single attempt.
+        assert_eq!(RetryPolicy::default().delay_after(1), None);
+
+        // Long runs of retries saturate instead of overflowing.
+        let retry = RetryPolicy {
+            max_attempts: u32::MAX,
+            backoff: Duration::from_secs(1),
+        };
+        let longest = Some(Duration::from_secs(u32::MAX.into()));
+        assert_eq!(retry.delay_after(40), longest);
+        assert_eq!(retry.delay_after(u32::MAX - 1), longest);
+    }
+
+    #[test]
+    fn test_outside_common_subsequence() {
+        let outside = |a: &str, b: &str| {
+            let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+            let mut outside = outside_common_subsequence(&a, &b)
+                .into_iter()
+                .collect::<Vec<_>>();
+            outside.sort();
+            outside.into_iter().collect::<String>()
+        };
+        assert_eq!(outside("abcde", "abcde"), "");
+        assert_eq!(outside("", ""), "");
+        // Moving one item flags only that item, in either direction.
+        assert_eq!(outside("abcde", "acdeb"), "b");
+        assert_eq!(outside("acdeb", "abcde"), "b");
+        assert_eq!(outside("abcde", "abecd"), "e");
+        // A swap flags one of the two.
+        assert_eq!(outside("bc", "cb"), "b");
+        assert_eq!(outside("abcdef", "bafedc").len(), 4);
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let row = |name: &str, secs: i64| PromadRow {
+            name: name.to_string(),
+            ordering_key: 0,
+            created_at: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            checksum: None,
+            applied_by: None,
+        };
+        assert!(clock_skew(&[]).is_none());
+        assert!(clock_skew(&[row("a", 10), row("b", 10), row("c", 20)]).is_none());
+
+        let rows = [row("a", 10), row("b", 30), row("c", 20), row("d", 5)];
+        let (before, after) = clock_skew(&rows).unwrap();
+        assert_eq!((&*before.name, &*after.name), ("b", "c"));
+    }
+
+    #[test]
+    fn test_timestamp_not_after() {
+        let cutoff = "20240315120000";
+
+        // A date alone counts as midnight, so only the day matters.
+        assert!(timestamp_not_after(20240314, cutoff));
+        assert!(timestamp_not_after(20240315, cutoff));
+        assert!(!timestamp_not_after(20240316, cutoff));
+
+        assert!(timestamp_not_after(20240315115959, cutoff));
+        assert!(timestamp_not_after(20240315120000, cutoff));
+        assert!(!timestamp_not_after(20240315120001, cutoff));
+
+        // Fractions of a second are within the cutoff's second.
+        assert!(timestamp_not_after(20240315120000999, cutoff));
+        assert!(timestamp_not_after(20230101000000123, cutoff));
+        assert!(!timestamp_not_after(20240315120001000, cutoff));
+
+        // Sequence numbers aren't timestamps, however they compare.
+        assert!(!timestamp_not_after(1, cutoff));
+        assert!(!timestamp_not_after(2, cutoff));
+        assert!(!timestamp_not_after(2024031, cutoff));
+    }
 }
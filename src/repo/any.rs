@@ -0,0 +1,559 @@
+// ┌───────────────────────────────────────────────────────────────────────────┐
+// │                                                                           │
+// │  ██████╗ ██████╗  ██████╗   Copyright (C) The Prospective Company         │
+// │  ██╔══██╗██╔══██╗██╔═══██╗  All Rights Reserved - April 2022              │
+// │  ██████╔╝██████╔╝██║   ██║                                                │
+// │  ██╔═══╝ ██╔══██╗██║   ██║  Proprietary and confidential. Unauthorized    │
+// │  ██║     ██║  ██║╚██████╔╝  copying of this file, via any medium is       │
+// │  ╚═╝     ╚═╝  ╚═╝ ╚═════╝   strictly prohibited.                          │
+// │                                                                           │
+// └───────────────────────────────────────────────────────────────────────────┘
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::{any::AnyRow, Any, AnyConnection};
+use sqlx::{Database, Row};
+
+use super::PromadRepo;
+use super::PromadRow;
+use super::DEFAULT_TABLE_NAME;
+use super::TABLE_FORMAT_VERSION;
+use crate::LockStrategy;
+
+/// The length of the name column on MySQL, which can't index unbounded text.
+const MYSQL_MAX_NAME_LENGTH: usize = 255;
+
+/// The databases `AnyPromadRepo` knows the dialect of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Postgres,
+    MySql,
+    Sqlite,
+}
+
+impl Backend {
+    fn of(conn: &AnyConnection) -> crate::error::Result<Self> {
+        match conn.backend_name() {
+            "PostgreSQL" => Ok(Backend::Postgres),
+            "MySQL" => Ok(Backend::MySql),
+            "SQLite" => Ok(Backend::Sqlite),
+            name => Err(crate::error::Error::UnsupportedBackend(name.to_string())),
+        }
+    }
+
+    /// Quote a possibly schema qualified identifier.
+    fn quote_ident(self, name: &str) -> String {
+        let quote = match self {
+            Backend::MySql => '`',
+            Backend::Postgres | Backend::Sqlite => '"',
+        };
+        name.split('.')
+            .map(|part| {
+                let escaped = part.replace(quote, &format!("{quote}{quote}"));
+                format!("{quote}{escaped}{quote}")
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Rewrite the `?` placeholders of `sql` into the backend's own.
+    fn placeholders(self, sql: &str) -> String {
+        if self != Backend::Postgres {
+            return sql.to_string();
+        }
+        let mut n = 0;
+        sql.chars()
+            .map(|c| match c {
+                '?' => {
+                    n += 1;
+                    format!("${n}")
+                }
+                c => c.to_string(),
+            })
+            .collect()
+    }
+
+    /// `created_at` as RFC 3339 text, since `Any` can't decode timestamps.
+    fn created_at_text(self) -> &'static str {
+        match self {
+            Backend::Postgres => {
+                r#"to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"')"#
+            }
+            Backend::MySql | Backend::Sqlite => "created_at",
+        }
+    }
+
+    /// The current time in the form `created_at` is stored in.
+    fn now(self) -> &'static str {
+        match self {
+            Backend::Postgres => "now()",
+            Backend::MySql => "DATE_FORMAT(UTC_TIMESTAMP(6), '%Y-%m-%dT%H:%i:%s.%fZ')",
+            Backend::Sqlite => "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')",
+        }
+    }
+}
+
+/// Tracks migrations on whichever database a `sqlx::Any` connection
+/// points at, for a single binary that picks the backend at runtime from
+/// the database URL. Postgres, MySQL and SQLite are supported, provided
+/// sqlx's driver for them is enabled and `sqlx::any::install_default_drivers`
+/// has been called before connecting.
+///
+/// On Postgres the table is the same as `PostgresPromadRepo`'s. Elsewhere
+/// `created_at` is stored as RFC 3339 text. Only tracking migrations is
+/// supported: the attempt log, migration state and schema snapshots need
+/// the backend's own repo. Advisory locks are Postgres and MySQL only;
+/// SQLite already serializes writers to the database file, so locking is
+/// a no-op there.
+#[derive(Debug)]
+pub struct AnyPromadRepo {
+    table_name: String,
+    log_sql: bool,
+    server_side_timestamps: bool,
+}
+
+impl Default for AnyPromadRepo {
+    fn default() -> Self {
+        Self {
+            table_name: DEFAULT_TABLE_NAME.to_string(),
+            log_sql: false,
+            server_side_timestamps: false,
+        }
+    }
+}
+
+impl AnyPromadRepo {
+    /// The tracking table, quoted for `backend`.
+    fn table(&self, backend: Backend) -> String {
+        backend.quote_ident(&self.table_name)
+    }
+
+    /// Holds the format version of the tables, quoted for `backend`.
+    fn meta_table(&self, backend: Backend) -> String {
+        backend.quote_ident(&format!("{}_meta", self.table_name))
+    }
+
+    fn init_sql(&self, backend: Backend) -> Vec<String> {
+        let table = self.table(backend);
+        match backend {
+            Backend::Postgres => {
+                let unqualified = self.table_name.rsplit('.').next().unwrap_or_default();
+                let index = backend.quote_ident(&format!(
+                    "idx_{}_ordering_key",
+                    unqualified.trim_start_matches('_')
+                ));
+                vec![
+                    format!(
+                        r#"CREATE TABLE IF NOT EXISTS {table} (
+        name TEXT NOT NULL PRIMARY KEY,
+        ordering_key BIGINT NOT NULL,
+        created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+        checksum TEXT,
+        applied_by TEXT
+    );"#
+                    ),
+                    format!("CREATE INDEX IF NOT EXISTS {index} ON {table} (ordering_key);"),
+                ]
+            }
+            Backend::MySql => vec![format!(
+                r#"CREATE TABLE IF NOT EXISTS {table} (
+        name VARCHAR({MYSQL_MAX_NAME_LENGTH}) NOT NULL PRIMARY KEY,
+        ordering_key BIGINT NOT NULL,
+        created_at VARCHAR(32) NOT NULL,
+        checksum TEXT,
+        applied_by TEXT
+    );"#
+            )],
+            Backend::Sqlite => vec![format!(
+                r#"CREATE TABLE IF NOT EXISTS {table} (
+        name TEXT NOT NULL PRIMARY KEY,
+        ordering_key INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        checksum TEXT,
+        applied_by TEXT
+    );"#
+            )],
+        }
+    }
+
+    /// Bring a tracking table laid out before `TABLE_FORMAT_VERSION` up to
+    /// date. Only run when the stored version is older, since `ALTER TABLE`
+    /// locks the table exclusively and needs its owner even when there's
+    /// nothing to change. MySQL and SQLite tables were created with every
+    /// column from the start.
+    fn upgrade_sql(&self, backend: Backend) -> Vec<String> {
+        let table = self.table(backend);
+        match backend {
+            Backend::Postgres => vec![
+                format!("ALTER TABLE {table} ADD COLUMN IF NOT EXISTS checksum TEXT;"),
+                format!("ALTER TABLE {table} ADD COLUMN IF NOT EXISTS applied_by TEXT;"),
+            ],
+            Backend::MySql | Backend::Sqlite => vec![],
+        }
+    }
+
+    /// The columns of a row, with `created_at` as text.
+    fn columns(&self, backend: Backend) -> String {
+        format!(
+            "name, ordering_key, {} AS created_at, checksum, applied_by",
+            backend.created_at_text()
+        )
+    }
+
+    fn log(&self, sql: &str) {
+        if self.log_sql {
+            tracing::info!(target: "promad::sql", "{}", sql);
+        }
+    }
+}
+
+/// Decode a row selected with `AnyPromadRepo::columns`.
+fn decode_row(row: &AnyRow) -> crate::error::Result<PromadRow> {
+    let created_at: String = row.try_get("created_at")?;
+    let created_at = DateTime::parse_from_rfc3339(&created_at)
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?
+        .with_timezone(&Utc);
+    Ok(PromadRow {
+        name: row.try_get("name")?,
+        ordering_key: row.try_get("ordering_key")?,
+        created_at,
+        checksum: row.try_get("checksum")?,
+        applied_by: row.try_get("applied_by")?,
+    })
+}
+
+#[async_trait]
+impl PromadRepo<Any> for AnyPromadRepo {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn set_sql_logging(&mut self, enabled: bool) {
+        self.log_sql = enabled;
+    }
+
+    fn set_server_side_timestamps(&mut self, enabled: bool) {
+        self.server_side_timestamps = enabled;
+    }
+
+    fn set_table_name(&mut self, table_name: &str) {
+        self.table_name = table_name.to_string();
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    async fn init<'a>(
+        &self,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let backend = Backend::of(conn)?;
+        let meta = self.meta_table(backend);
+        let sql = format!(
+            r#"CREATE TABLE IF NOT EXISTS {meta} (
+        id INT PRIMARY KEY CHECK (id = 1),
+        format_version INT NOT NULL
+    );"#
+        );
+        self.log(&sql);
+        sqlx::query(&sql).execute(&mut *conn).await?;
+        let sql = format!("SELECT format_version FROM {meta} WHERE id = 1");
+        self.log(&sql);
+        let found: Option<i32> = sqlx::query_scalar(&sql).fetch_optional(&mut *conn).await?;
+        if let Some(found) = found.filter(|&x| x > TABLE_FORMAT_VERSION) {
+            return Err(crate::error::Error::UnsupportedTableFormat {
+                found,
+                supported: TABLE_FORMAT_VERSION,
+            });
+        }
+
+        for sql in self.init_sql(backend) {
+            self.log(&sql);
+            sqlx::query(&sql).execute(&mut *conn).await?;
+        }
+
+        if found == Some(TABLE_FORMAT_VERSION) {
+            return Ok(());
+        }
+        for sql in self.upgrade_sql(backend) {
+            self.log(&sql);
+            sqlx::query(&sql).execute(&mut *conn).await?;
+        }
+
+        let upsert = match backend {
+            Backend::MySql => "ON DUPLICATE KEY UPDATE format_version = VALUES(format_version)",
+            Backend::Postgres | Backend::Sqlite => {
+                "ON CONFLICT (id) DO UPDATE SET format_version = excluded.format_version"
+            }
+        };
+        let sql = backend.placeholders(&format!(
+            "INSERT INTO {meta} (id, format_version) VALUES (1, ?) {upsert}"
+        ));
+        self.log(&sql);
+        sqlx::query(&sql)
+            .bind(TABLE_FORMAT_VERSION)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    // MySQL and SQLite can't make a transaction read only once it has
+    // begun, so only Postgres enforces it.
+    async fn set_read_only<'a>(
+        &self,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        if Backend::of(conn)? == Backend::Postgres {
+            let sql = "SET TRANSACTION READ ONLY";
+            self.log(sql);
+            sqlx::query(sql).execute(conn).await?;
+        }
+        Ok(())
+    }
+
+    async fn lock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let backend = Backend::of(conn)?;
+        let sql = match (backend, strategy) {
+            (_, LockStrategy::None) | (Backend::Sqlite, _) => return Ok(()),
+            (Backend::Postgres, LockStrategy::Advisory(key)) => {
+                format!("SELECT pg_advisory_lock({key})")
+            }
+            (Backend::Postgres, LockStrategy::AdvisoryXact(key)) => {
+                format!("SELECT pg_advisory_xact_lock({key})")
+            }
+            (Backend::Postgres, LockStrategy::TableLock) => format!(
+                "LOCK TABLE {} IN SHARE UPDATE EXCLUSIVE MODE",
+                self.table(backend)
+            ),
+            (Backend::MySql, LockStrategy::Advisory(key)) => {
+                format!("SELECT GET_LOCK('promad_{key}', -1)")
+            }
+            (Backend::MySql, _) => {
+                return Err(crate::error::Error::LockUnsupported(format!(
+                    "{strategy:?} on MySQL"
+                )))
+            }
+        };
+        self.log(&sql);
+        sqlx::query(&sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn unlock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let LockStrategy::Advisory(key) = strategy else {
+            return Ok(());
+        };
+        let sql = match Backend::of(conn)? {
+            Backend::Postgres => format!("SELECT pg_advisory_unlock({key})"),
+            Backend::MySql => format!("SELECT RELEASE_LOCK('promad_{key}')"),
+            Backend::Sqlite => return Ok(()),
+        };
+        self.log(&sql);
+        sqlx::query(&sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn execute<'a>(
+        &self,
+        sql: &str,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.log(sql);
+        sqlx::query(sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn max_name_length<'a>(
+        &self,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<Option<usize>> {
+        Ok(match Backend::of(conn)? {
+            Backend::MySql => Some(MYSQL_MAX_NAME_LENGTH),
+            Backend::Postgres | Backend::Sqlite => None,
+        })
+    }
+
+    async fn notify<'a>(
+        &self,
+        channel: &str,
+        payload: &str,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        if Backend::of(conn)? != Backend::Postgres {
+            return Ok(());
+        }
+        let sql = "SELECT pg_notify($1, $2)";
+        self.log(sql);
+        sqlx::query(sql)
+            .bind(channel)
+            .bind(payload)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn exists<'a>(
+        &self,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        let backend = Backend::of(conn)?;
+        let (sql, name) = match backend {
+            Backend::Postgres => (
+                "SELECT COUNT(*) FROM (SELECT to_regclass($1) AS t) regclass WHERE t IS NOT NULL",
+                self.table(backend),
+            ),
+            Backend::MySql => (
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = ?",
+                self.table_name.clone(),
+            ),
+            Backend::Sqlite => (
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+                self.table_name.clone(),
+            ),
+        };
+        self.log(sql);
+        let (count,): (i64,) = sqlx::query_as(sql).bind(name).fetch_one(conn).await?;
+        Ok(count > 0)
+    }
+
+    async fn get_all<'a>(
+        &self,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<Vec<PromadRow>> {
+        let backend = Backend::of(conn)?;
+        let sql = format!(
+            "SELECT {} FROM {} ORDER BY ordering_key",
+            self.columns(backend),
+            self.table(backend)
+        );
+        self.log(&sql);
+        sqlx::query(&sql)
+            .fetch_all(conn)
+            .await?
+            .iter()
+            .map(decode_row)
+            .collect()
+    }
+
+    async fn get<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<Option<PromadRow>> {
+        let backend = Backend::of(conn)?;
+        let sql = backend.placeholders(&format!(
+            "SELECT {} FROM {} WHERE name = ?",
+            self.columns(backend),
+            self.table(backend)
+        ));
+        self.log(&sql);
+        sqlx::query(&sql)
+            .bind(name)
+            .fetch_optional(conn)
+            .await?
+            .as_ref()
+            .map(decode_row)
+            .transpose()
+    }
+
+    async fn insert<'a>(
+        &self,
+        row: &PromadRow,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        let backend = Backend::of(conn)?;
+        let created_at = match (self.server_side_timestamps, backend) {
+            (true, _) => backend.now(),
+            (false, Backend::Postgres) => "CAST(? AS TIMESTAMP WITH TIME ZONE)",
+            (false, _) => "?",
+        };
+        let (insert, on_conflict) = match backend {
+            Backend::MySql => ("INSERT IGNORE", ""),
+            Backend::Postgres | Backend::Sqlite => ("INSERT", " ON CONFLICT (name) DO NOTHING"),
+        };
+        let sql = backend.placeholders(&format!(
+            "{insert} INTO {} (name, ordering_key, created_at, checksum, applied_by) VALUES (?, ?, {created_at}, ?, ?){on_conflict}",
+            self.table(backend)
+        ));
+        self.log(&sql);
+        let mut query = sqlx::query(&sql)
+            .bind(row.name.clone())
+            .bind(row.ordering_key);
+        if !self.server_side_timestamps {
+            query = query.bind(row.created_at.to_rfc3339_opts(SecondsFormat::Micros, true));
+        }
+        let res = query
+            .bind(row.checksum.clone())
+            .bind(row.applied_by.clone())
+            .execute(conn)
+            .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn clear<'a>(
+        &self,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!("DELETE FROM {}", self.table(Backend::of(conn)?));
+        self.log(&sql);
+        sqlx::query(&sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn drop_table<'a>(
+        &self,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let backend = Backend::of(conn)?;
+        for table in [self.table(backend), self.meta_table(backend)] {
+            let sql = format!("DROP TABLE IF EXISTS {table}");
+            self.log(&sql);
+            sqlx::query(&sql).execute(&mut *conn).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let backend = Backend::of(conn)?;
+        let sql = backend.placeholders(&format!(
+            "DELETE FROM {} WHERE name = ?",
+            self.table(backend)
+        ));
+        self.log(&sql);
+        sqlx::query(&sql).bind(name).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn update_checksum<'a>(
+        &self,
+        name: &str,
+        checksum: Option<&str>,
+        conn: &'a mut <Any as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let backend = Backend::of(conn)?;
+        let sql = backend.placeholders(&format!(
+            "UPDATE {} SET checksum = ? WHERE name = ?",
+            self.table(backend)
+        ));
+        self.log(&sql);
+        sqlx::query(&sql)
+            .bind(checksum.map(str::to_string))
+            .bind(name)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+// ┌───────────────────────────────────────────────────────────────────────────┐
+// │                                                                           │
+// │  ██████╗ ██████╗  ██████╗   Copyright (C) The Prospective Company         │
+// │  ██╔══██╗██╔══██╗██╔═══██╗  All Rights Reserved - April 2022              │
+// │  ██████╔╝██████╔╝██║   ██║                                                │
+// │  ██╔═══╝ ██╔══██╗██║   ██║  Proprietary and confidential. Unauthorized    │
+// │  ██║     ██║  ██║╚██████╔╝  copying of this file, via any medium is       │
+// │  ╚═╝     ╚═╝  ╚═╝ ╚═════╝   strictly prohibited.                          │
+// │                                                                           │
+// └───────────────────────────────────────────────────────────────────────────┘
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use sqlx::{Database, Executor};
+
+use super::PromadRepo;
+use super::PromadRow;
+
+/// Keeps the migrations table in memory rather than in the database.
+/// Useful for testing ordering and validation logic and for
+/// environments without a tracking table. The connections passed in are
+/// only used for the statements that configure the migrations' own
+/// transactions and sessions: isolation levels, roles and session hooks,
+/// written as Postgres does.
+///
+/// Writes take effect straight away, outside of any transaction, so a
+/// migration that fails to commit can still be recorded. Migrators
+/// refuse dry runs with it, since nothing would be rolled back.
+///
+/// Clones share the same underlying table. Like the tables in the
+/// database, it holds at most one row per name.
+pub struct MemoryPromadRepo<DB: Database> {
+    rows: Arc<RwLock<BTreeMap<String, PromadRow>>>,
+    state: Arc<RwLock<BTreeMap<(String, String), String>>>,
+    _marker: std::marker::PhantomData<fn() -> DB>,
+}
+
+impl<DB: Database> Clone for MemoryPromadRepo<DB> {
+    fn clone(&self) -> Self {
+        Self {
+            rows: self.rows.clone(),
+            state: self.state.clone(),
+            _marker: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<DB: Database> PromadRepo<DB> for MemoryPromadRepo<DB>
+where
+    for<'c> &'c mut <DB as Database>::Connection: Executor<'c, Database = DB>,
+{
+    fn new() -> Self {
+        Self {
+            rows: Arc::new(RwLock::new(BTreeMap::new())),
+            state: Arc::new(RwLock::new(BTreeMap::new())),
+            _marker: Default::default(),
+        }
+    }
+
+    fn is_transactional(&self) -> bool {
+        false
+    }
+
+    async fn init<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+
+    // Nothing here touches the connection, so there's nothing to enforce.
+    async fn set_read_only<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+
+    async fn set_isolation<'a>(
+        &self,
+        level: crate::IsolationLevel,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!("SET TRANSACTION ISOLATION LEVEL {}", level.as_sql());
+        conn.execute(&*sql).await?;
+        Ok(())
+    }
+
+    async fn set_role<'a>(
+        &self,
+        role: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        conn.execute(&*format!("SET LOCAL ROLE {role}")).await?;
+        Ok(())
+    }
+
+    async fn execute<'a>(
+        &self,
+        sql: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        conn.execute(sql).await?;
+        Ok(())
+    }
+
+    async fn get_all<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<PromadRow>> {
+        let mut rows = self.rows.read()?.values().cloned().collect::<Vec<_>>();
+        rows.sort_by_key(|row| row.ordering_key);
+        Ok(rows)
+    }
+
+    async fn get<'a>(
+        &self,
+        name: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<PromadRow>> {
+        let rows = self.rows.read()?;
+        Ok(rows.get(name).cloned())
+    }
+
+    async fn insert<'a>(
+        &self,
+        row: &PromadRow,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        let mut rows = self.rows.write()?;
+        if rows.contains_key(&row.name) {
+            return Ok(false);
+        }
+        rows.insert(row.name.clone(), row.clone());
+        Ok(true)
+    }
+
+    async fn insert_many<'a>(
+        &self,
+        rows: &[PromadRow],
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let mut table = self.rows.write()?;
+        for row in rows {
+            table.entry(row.name.clone()).or_insert_with(|| row.clone());
+        }
+        Ok(())
+    }
+
+    async fn clear<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.rows.write()?.clear();
+        Ok(())
+    }
+
+    async fn delete<'a>(
+        &self,
+        name: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.rows.write()?.remove(name);
+        Ok(())
+    }
+
+    async fn drop_table<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.rows.write()?.clear();
+        self.state.write()?.clear();
+        Ok(())
+    }
+
+    async fn update_checksum<'a>(
+        &self,
+        name: &str,
+        checksum: Option<&str>,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        if let Some(row) = self.rows.write()?.get_mut(name) {
+            row.checksum = checksum.map(Into::into);
+        }
+        Ok(())
+    }
+
+    async fn state_get<'a>(
+        &self,
+        migration: &str,
+        key: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<String>> {
+        let state = self.state.read()?;
+        Ok(state.get(&(migration.into(), key.into())).cloned())
+    }
+
+    async fn state_set<'a>(
+        &self,
+        migration: &str,
+        key: &str,
+        value: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let mut state = self.state.write()?;
+        state.insert((migration.into(), key.into()), value.into());
+        Ok(())
+    }
+
+    async fn state_clear<'a>(
+        &self,
+        migration: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let mut state = self.state.write()?;
+        state.retain(|(name, _), _| name != migration);
+        Ok(())
+    }
+}
+
+// The repo never uses the connections, but they have to come from somewhere,
+// and SQLite's in-memory databases don't need a server.
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use sqlx::{Connection, Sqlite, SqliteConnection};
+
+    fn row(name: &str, ordering_key: i64) -> PromadRow {
+        PromadRow {
+            name: name.to_string(),
+            ordering_key,
+            created_at: Utc::now(),
+            checksum: None,
+            applied_by: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_get_delete() -> crate::error::Result<()> {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await?;
+        let repo = MemoryPromadRepo::<Sqlite>::new();
+        assert!(repo.get_all(&mut conn).await?.is_empty());
+
+        assert!(repo.insert(&row("second", 2), &mut conn).await?);
+        assert!(repo.insert(&row("first", 1), &mut conn).await?);
+        // A name that's already recorded isn't inserted again.
+        assert!(!repo.insert(&row("first", 3), &mut conn).await?);
+
+        assert_eq!(repo.get("first", &mut conn).await?.unwrap().ordering_key, 1);
+        assert!(repo.get("missing", &mut conn).await?.is_none());
+        let names = |rows: Vec<PromadRow>| rows.into_iter().map(|x| x.name).collect::<Vec<_>>();
+        assert_eq!(names(repo.get_all(&mut conn).await?), ["first", "second"]);
+
+        repo.delete("first", &mut conn).await?;
+        assert!(repo.get("first", &mut conn).await?.is_none());
+        assert_eq!(names(repo.get_all(&mut conn).await?), ["second"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rows_keyed_by_name() -> crate::error::Result<()> {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await?;
+        let repo = MemoryPromadRepo::<Sqlite>::new();
+        // Sharing a key doesn't make one row replace the other.
+        assert!(repo.insert(&row("first", 1), &mut conn).await?);
+        assert!(repo.insert(&row("other", 1), &mut conn).await?);
+
+        // Names already recorded are skipped, including repeats in the batch.
+        let batch = [row("first", 5), row("second", 2), row("second", 6)];
+        repo.insert_many(&batch, &mut conn).await?;
+        let rows = repo
+            .get_all(&mut conn)
+            .await?
+            .into_iter()
+            .map(|x| (x.name, x.ordering_key))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                ("first".to_string(), 1),
+                ("other".to_string(), 1),
+                ("second".to_string(), 2)
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_table() -> crate::error::Result<()> {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await?;
+        let repo = MemoryPromadRepo::<Sqlite>::new();
+        let clone = repo.clone();
+        repo.insert(&row("first", 1), &mut conn).await?;
+        clone.state_set("first", "cursor", "50", &mut conn).await?;
+
+        assert!(clone.get("first", &mut conn).await?.is_some());
+        assert_eq!(
+            repo.state_get("first", "cursor", &mut conn)
+                .await?
+                .as_deref(),
+            Some("50")
+        );
+        clone.delete("first", &mut conn).await?;
+        assert!(repo.get_all(&mut conn).await?.is_empty());
+
+        // A new repo has a table of its own.
+        let other = MemoryPromadRepo::<Sqlite>::new();
+        repo.insert(&row("second", 2), &mut conn).await?;
+        assert!(other.get_all(&mut conn).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrator() -> crate::error::Result<()> {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await?;
+        let repo = MemoryPromadRepo::<Sqlite>::new();
+        let mut migrator = crate::Migrator::create_with_repo(
+            pool.clone(),
+            Box::new(repo.clone()),
+            Box::new(|_| Box::new(crate::NullMigrationUI)),
+        );
+        migrator.add_migration(Box::new(crate::SqlFileMigration::new(
+            "first",
+            "SELECT 1",
+            Some("SELECT 1"),
+        )))?;
+        migrator.apply_all().await?;
+        assert!(migrator.is_applied("first").await?);
+        assert_eq!(migrator.pending_count().await?, 0);
+
+        // Reverting for real would remove the row, so a dry run can't be
+        // allowed to.
+        let migrator = migrator.with_dry_run(true);
+        assert!(matches!(
+            migrator.revert_all().await,
+            Err(crate::error::Error::DryRunUnsupported)
+        ));
+        let mut conn = pool.acquire().await?;
+        assert!(repo.get("first", &mut conn).await?.is_some());
+        Ok(())
+    }
+}
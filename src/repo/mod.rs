@@ -17,14 +17,50 @@ use std::{
 use async_trait::async_trait;
 use sqlx::Database;
 
+use crate::{Direction, IsolationLevel, LockStrategy};
+
+#[cfg(feature = "any")]
+pub mod any;
+pub mod memory;
 #[cfg(feature = "postgres")]
 pub mod postgres;
 
+/// Table migrations are tracked in unless another is configured.
+pub const DEFAULT_TABLE_NAME: &str = "_promad";
+
+/// Version of the layout of the tables promad keeps, stored in the
+/// `<table>_meta` table by `init`. Bumped whenever a change would make
+/// older versions of promad misread the tables, so they refuse to run,
+/// and whenever existing tables need upgrading, so `init` knows to do it.
+pub const TABLE_FORMAT_VERSION: i32 = 2;
+
 #[derive(sqlx::FromRow, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PromadRow {
-    pub(crate) name: String,
-    pub(crate) ordering_key: i64,
-    pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+    /// Name of the applied migration.
+    pub name: String,
+    /// Key the migration was applied with, which rows are ordered by.
+    pub ordering_key: i64,
+    /// When the migration was applied.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Checksum of the migration in `algo:hex` form, if it provided one.
+    pub checksum: Option<String>,
+    /// Who or what applied the migration, for audit trails.
+    pub applied_by: Option<String>,
+}
+
+/// One attempt at running a migration, kept whether or not it succeeded.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct PromadAttempt {
+    pub id: i64,
+    pub name: String,
+    /// `up` or `down`.
+    pub direction: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// When the attempt ended, unless the process died first.
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Why the attempt failed, if it did.
+    pub error: Option<String>,
 }
 
 /// A trait for interacting with the migrations table
@@ -34,16 +70,75 @@ pub trait PromadRepo<DB: Database>: Send + Sync {
     fn new() -> Self
     where
         Self: Sized;
-    /// Creates the migrations table if it does not exist.
+    /// Creates the migrations table if it does not exist. Fails with
+    /// `Error::UnsupportedTableFormat`, before changing anything else, if
+    /// the tables were laid out by a newer promad.
     async fn init<'a>(
         &self,
         conn: &'a mut <DB as Database>::Connection,
     ) -> crate::error::Result<()>;
-    /// Set the current transaction to read only.
+    /// Set the current transaction to read only. Called before anything
+    /// else runs in the transaction. Backends that can't enforce it return
+    /// `Error::ReadOnlyUnsupported`.
     async fn set_read_only<'a>(
         &self,
-        conn: &'a mut <DB as Database>::Connection,
-    ) -> crate::error::Result<()>;
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Err(crate::error::Error::ReadOnlyUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Set the isolation level of the current transaction. Called right
+    /// after the transaction begins, before `set_read_only`.
+    async fn set_isolation<'a>(
+        &self,
+        _level: IsolationLevel,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Err(crate::error::Error::IsolationUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Run the rest of the current transaction as `role`. The name has
+    /// already been checked to be a plain identifier.
+    async fn set_role<'a>(
+        &self,
+        _role: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Err(crate::error::Error::RoleUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Run one of a migration's session setup or teardown statements.
+    async fn execute<'a>(
+        &self,
+        _sql: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Err(crate::error::Error::SessionHooksUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Tell listeners on `channel` that the migration named `payload` was
+    /// applied or reverted, once the current transaction commits. Does
+    /// nothing on databases without notifications.
+    async fn notify<'a>(
+        &self,
+        _channel: &str,
+        _payload: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+    /// The longest migration name the table can hold, in characters, if
+    /// its primary key is limited.
+    async fn max_name_length<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<usize>> {
+        Ok(None)
+    }
     /// Return the rows ordered by `ordering_key`.
     async fn get_all<'a>(
         &self,
@@ -55,27 +150,246 @@ pub trait PromadRepo<DB: Database>: Send + Sync {
         name: &str,
         conn: &'a mut <DB as Database>::Connection,
     ) -> crate::error::Result<Option<PromadRow>>;
-    /// Insert a new migration.
+    /// Get specific migration by name from the database, leaving any cache
+    /// as it is.
+    async fn get_uncached<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<PromadRow>> {
+        self.get(name, conn).await
+    }
+    /// Insert a new migration, unless a migration with the same name has
+    /// already been recorded. Returns whether the row was inserted, so that
+    /// losing a race to record a migration isn't an error.
     async fn insert<'a>(
         &self,
         row: &PromadRow,
         conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<bool>;
+    /// Insert several migrations at once, skipping names that are already
+    /// recorded as `insert` does. Implementations should do this in a single
+    /// round trip where they can.
+    async fn insert_many<'a>(
+        &self,
+        rows: &[PromadRow],
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        for row in rows {
+            self.insert(row, &mut *conn).await?;
+        }
+        Ok(())
+    }
+    /// Remove every migration.
+    async fn clear<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
     ) -> crate::error::Result<()>;
-    /// Remove a migration.
+    /// Remove the row of the migration called `name`.
     async fn delete<'a>(
         &self,
-        row: &'static str,
+        name: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()>;
+    /// Drop the migrations table and everything else `init` created, as if
+    /// promad had never run.
+    async fn drop_table<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Err(crate::error::Error::DropUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Replace the stored checksum of a migration.
+    async fn update_checksum<'a>(
+        &self,
+        name: &str,
+        checksum: Option<&str>,
         conn: &'a mut <DB as Database>::Connection,
     ) -> crate::error::Result<()>;
+    /// Take the migration lock on `conn` using the given strategy. For the
+    /// transaction scoped strategies `conn` is inside a transaction that's
+    /// kept open until the lock should be released.
+    async fn lock<'a>(
+        &self,
+        _strategy: &LockStrategy,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+    /// Release a lock taken with a session scoped strategy.
+    async fn unlock<'a>(
+        &self,
+        _strategy: &LockStrategy,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+    /// Record that a migration is about to run, returning the id to
+    /// finish the attempt with. Backends without an attempt log, or with it
+    /// turned off, return `None`.
+    async fn start_attempt<'a>(
+        &self,
+        _name: &str,
+        _direction: Direction,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<i64>> {
+        Ok(None)
+    }
+    /// Record how an attempt started with `start_attempt` ended.
+    async fn finish_attempt<'a>(
+        &self,
+        _id: i64,
+        _error: Option<&str>,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+    /// The value a migration saved under `key`, kept outside its
+    /// transaction so it survives a failed attempt.
+    async fn state_get<'a>(
+        &self,
+        _migration: &str,
+        _key: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<String>> {
+        Err(crate::error::Error::StateUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Save `value` under `key` for a migration, replacing any value
+    /// already there.
+    async fn state_set<'a>(
+        &self,
+        _migration: &str,
+        _key: &str,
+        _value: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Err(crate::error::Error::StateUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Forget everything a migration saved, once it's run to completion.
+    /// Backends without state have nothing to forget.
+    async fn state_clear<'a>(
+        &self,
+        _migration: &str,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+    /// Every recorded attempt, oldest first.
+    async fn attempts<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<PromadAttempt>> {
+        Ok(vec![])
+    }
+    /// Whether the migrations table has been created yet.
+    async fn exists<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        Ok(true)
+    }
+    /// Check that the lock could be taken right now without waiting for it.
+    /// Any lock taken to find out is released again before returning.
+    async fn try_lock<'a>(
+        &self,
+        _strategy: &LockStrategy,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        Ok(true)
+    }
+    /// Identifier of the database session behind `conn`, if the backend
+    /// can report which sessions block others.
+    async fn session_id<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<i64>> {
+        Ok(None)
+    }
+    /// Sessions holding locks that `session_id` is waiting for.
+    async fn blocking_sessions<'a>(
+        &self,
+        _session_id: i64,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<i64>> {
+        Ok(vec![])
+    }
+    /// A sorted description of the schema, one line per table, column,
+    /// index and constraint, for telling whether two schemas differ.
+    async fn schema_snapshot<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<String>> {
+        Err(crate::error::Error::SchemaSnapshotUnsupported(
+            std::any::type_name::<DB>().to_string(),
+        ))
+    }
+    /// Number of rows inserted, updated or deleted so far in the current
+    /// transaction. Backends that can't tell return 0.
+    async fn rows_changed<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<i64> {
+        Ok(0)
+    }
+    /// Check that the connection is allowed to create and drop tables.
+    async fn check_ddl<'a>(
+        &self,
+        _conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        Ok(())
+    }
+    /// Whether writes are part of the transaction on the connection they're
+    /// made through, so rolling it back undoes them. Dry runs rely on it.
+    fn is_transactional(&self) -> bool {
+        true
+    }
+    /// Name of the table migrations are tracked in.
+    fn table_name(&self) -> &str {
+        DEFAULT_TABLE_NAME
+    }
+    /// Track migrations in a different table, optionally schema qualified.
+    fn set_table_name(&mut self, _table_name: &str) {}
+    /// Drop any cached state so the next read goes to the database.
+    fn invalidate(&self) -> crate::error::Result<()> {
+        Ok(())
+    }
+    /// Log the statements the repo writes with through `tracing`.
+    fn set_sql_logging(&mut self, _enabled: bool) {}
+    /// Let the database fill in `created_at` on insert instead of using the
+    /// timestamp of the row passed in.
+    fn set_server_side_timestamps(&mut self, _enabled: bool) {}
+    /// Keep a log of every attempt to run a migration, including failed ones.
+    fn set_attempt_log(&mut self, _enabled: bool) {}
 }
 
+/// Caches the migrations table after it has been loaded once. The cache
+/// isn't aware of changes made by other processes, so call `invalidate`
+/// before an operation that needs to see the current state of the database.
 pub struct CachedPromadRepo<DB: Database, N: PromadRepo<DB>> {
     inner: Box<dyn PromadRepo<DB>>,
-    cache: Arc<RwLock<BTreeMap<i64, PromadRow>>>,
+    cache: Arc<RwLock<BTreeMap<String, PromadRow>>>,
     is_db_loaded: Arc<RwLock<bool>>,
+    server_side_timestamps: bool,
     _marker: std::marker::PhantomData<N>,
 }
 
+impl<DB: Database, N: PromadRepo<DB> + 'static> CachedPromadRepo<DB, N> {
+    /// Read all rows from the database, bypassing and refreshing the cache.
+    pub async fn get_all_uncached(
+        &self,
+        conn: &mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<PromadRow>> {
+        self.invalidate()?;
+        self.get_all(conn).await
+    }
+}
+
 #[async_trait]
 impl<DB: Database, N: PromadRepo<DB> + 'static> PromadRepo<DB> for CachedPromadRepo<DB, N> {
     fn new() -> Self {
@@ -83,6 +397,7 @@ impl<DB: Database, N: PromadRepo<DB> + 'static> PromadRepo<DB> for CachedPromadR
             inner: Box::new(N::new()),
             cache: Arc::new(RwLock::new(BTreeMap::new())),
             is_db_loaded: Arc::new(RwLock::new(false)),
+            server_side_timestamps: false,
             _marker: Default::default(),
         }
     }
@@ -101,6 +416,46 @@ impl<DB: Database, N: PromadRepo<DB> + 'static> PromadRepo<DB> for CachedPromadR
         self.inner.set_read_only(conn).await
     }
 
+    async fn set_isolation<'a>(
+        &self,
+        level: IsolationLevel,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.set_isolation(level, conn).await
+    }
+
+    async fn set_role<'a>(
+        &self,
+        role: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.set_role(role, conn).await
+    }
+
+    async fn execute<'a>(
+        &self,
+        sql: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.execute(sql, conn).await
+    }
+
+    async fn notify<'a>(
+        &self,
+        channel: &str,
+        payload: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.notify(channel, payload, conn).await
+    }
+
+    async fn max_name_length<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<usize>> {
+        self.inner.max_name_length(conn).await
+    }
+
     async fn get_all<'a>(
         &self,
         conn: &'a mut <DB as Database>::Connection,
@@ -108,17 +463,21 @@ impl<DB: Database, N: PromadRepo<DB> + 'static> PromadRepo<DB> for CachedPromadR
         {
             let is_db_loaded = self.is_db_loaded.read()?;
             if *is_db_loaded {
-                let cache = self.cache.read()?;
-                return Ok(cache.values().cloned().collect());
+                let mut rows = self.cache.read()?.values().cloned().collect::<Vec<_>>();
+                rows.sort_by_key(|row| row.ordering_key);
+                return Ok(rows);
             }
         }
 
+        // Replace rather than extend the cache so nothing written while it
+        // wasn't loaded can linger next to the rows actually in the database.
         let rows = {
             let rows = self.inner.get_all(conn).await?;
             let mut cache = self.cache.write()?;
-            for row in &rows {
-                cache.insert(row.ordering_key, row.clone());
-            }
+            *cache = rows
+                .iter()
+                .map(|row| (row.name.clone(), row.clone()))
+                .collect();
             rows
         };
 
@@ -138,38 +497,247 @@ impl<DB: Database, N: PromadRepo<DB> + 'static> PromadRepo<DB> for CachedPromadR
             let is_db_loaded = self.is_db_loaded.read()?;
             if *is_db_loaded {
                 let cache = self.cache.read()?;
-                return Ok(cache.values().find(|&row| row.name == name).cloned());
+                return Ok(cache.get(name).cloned());
             }
         }
 
-        let row = self.inner.get(name, conn).await?;
-        if let Some(ref r) = row {
-            let mut cache = self.cache.write()?;
-            cache.insert(r.ordering_key, r.clone());
-        }
+        // A single row doesn't tell us anything about the rest of the table,
+        // so only a full `get_all` populates the cache.
+        self.inner.get(name, conn).await
+    }
 
-        Ok(row)
+    async fn get_uncached<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<PromadRow>> {
+        self.inner.get(name, conn).await
     }
 
     async fn insert<'a>(
         &self,
         row: &PromadRow,
         conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        let inserted = self.inner.insert(row, conn).await?;
+        if self.server_side_timestamps || !inserted {
+            // Only the database knows the timestamp it stored, or the row
+            // another process recorded, so reload the table the next time
+            // it's needed.
+            self.invalidate()?;
+            return Ok(inserted);
+        }
+        let mut cache = self.cache.write()?;
+        cache.insert(row.name.clone(), row.clone());
+        Ok(true)
+    }
+
+    async fn insert_many<'a>(
+        &self,
+        rows: &[PromadRow],
+        conn: &'a mut <DB as Database>::Connection,
     ) -> crate::error::Result<()> {
-        self.inner.insert(row, conn).await?;
+        self.inner.insert_many(rows, conn).await?;
+        if self.server_side_timestamps {
+            return self.invalidate();
+        }
+        // Rows whose names were already recorded were skipped, as they are
+        // in the database.
         let mut cache = self.cache.write()?;
-        cache.insert(row.ordering_key, row.clone());
+        for row in rows {
+            cache.entry(row.name.clone()).or_insert_with(|| row.clone());
+        }
+        Ok(())
+    }
+
+    async fn clear<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.clear(conn).await?;
+        self.cache.write()?.clear();
         Ok(())
     }
 
+    async fn drop_table<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.drop_table(conn).await?;
+        self.invalidate()
+    }
+
     async fn delete<'a>(
         &self,
-        name: &'static str,
+        name: &str,
         conn: &'a mut <DB as Database>::Connection,
     ) -> crate::error::Result<()> {
         self.inner.delete(name, conn).await?;
-        let mut cache = self.cache.write()?;
-        cache.retain(|_, row| row.name != name);
+        self.cache.write()?.remove(name);
+        Ok(())
+    }
+
+    async fn update_checksum<'a>(
+        &self,
+        name: &str,
+        checksum: Option<&str>,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.update_checksum(name, checksum, conn).await?;
+        if let Some(row) = self.cache.write()?.get_mut(name) {
+            row.checksum = checksum.map(Into::into);
+        }
+        Ok(())
+    }
+
+    async fn lock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.lock(strategy, conn).await
+    }
+
+    async fn unlock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.unlock(strategy, conn).await
+    }
+
+    async fn start_attempt<'a>(
+        &self,
+        name: &str,
+        direction: Direction,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<i64>> {
+        self.inner.start_attempt(name, direction, conn).await
+    }
+
+    async fn finish_attempt<'a>(
+        &self,
+        id: i64,
+        error: Option<&str>,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.finish_attempt(id, error, conn).await
+    }
+
+    async fn attempts<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<PromadAttempt>> {
+        self.inner.attempts(conn).await
+    }
+
+    async fn state_get<'a>(
+        &self,
+        migration: &str,
+        key: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<String>> {
+        self.inner.state_get(migration, key, conn).await
+    }
+
+    async fn state_set<'a>(
+        &self,
+        migration: &str,
+        key: &str,
+        value: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.state_set(migration, key, value, conn).await
+    }
+
+    async fn state_clear<'a>(
+        &self,
+        migration: &str,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.state_clear(migration, conn).await
+    }
+
+    async fn exists<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        self.inner.exists(conn).await
+    }
+
+    async fn try_lock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        self.inner.try_lock(strategy, conn).await
+    }
+
+    async fn session_id<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Option<i64>> {
+        self.inner.session_id(conn).await
+    }
+
+    async fn blocking_sessions<'a>(
+        &self,
+        session_id: i64,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<i64>> {
+        self.inner.blocking_sessions(session_id, conn).await
+    }
+
+    async fn check_ddl<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.inner.check_ddl(conn).await
+    }
+
+    async fn schema_snapshot<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<Vec<String>> {
+        self.inner.schema_snapshot(conn).await
+    }
+
+    async fn rows_changed<'a>(
+        &self,
+        conn: &'a mut <DB as Database>::Connection,
+    ) -> crate::error::Result<i64> {
+        self.inner.rows_changed(conn).await
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.inner.is_transactional()
+    }
+
+    fn table_name(&self) -> &str {
+        self.inner.table_name()
+    }
+
+    fn set_table_name(&mut self, table_name: &str) {
+        self.inner.set_table_name(table_name);
+    }
+
+    fn set_sql_logging(&mut self, enabled: bool) {
+        self.inner.set_sql_logging(enabled);
+    }
+
+    fn set_server_side_timestamps(&mut self, enabled: bool) {
+        self.server_side_timestamps = enabled;
+        self.inner.set_server_side_timestamps(enabled);
+    }
+
+    fn set_attempt_log(&mut self, enabled: bool) {
+        self.inner.set_attempt_log(enabled);
+    }
+
+    fn invalidate(&self) -> crate::error::Result<()> {
+        let mut is_db_loaded = self.is_db_loaded.write()?;
+        *is_db_loaded = false;
+        self.cache.write()?.clear();
         Ok(())
     }
 }
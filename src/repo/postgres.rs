@@ -12,54 +12,573 @@
 use async_trait::async_trait;
 use sqlx::Database;
 use sqlx::Postgres;
+use sqlx::QueryBuilder;
 
+use super::PromadAttempt;
 use super::PromadRepo;
 use super::PromadRow;
+use super::DEFAULT_TABLE_NAME;
+use super::TABLE_FORMAT_VERSION;
+use crate::{Direction, IsolationLevel, LockStrategy};
 
-const INIT_SQL: &[&str] = &[
-    r#"CREATE TABLE IF NOT EXISTS _promad (
+#[derive(Debug)]
+pub struct PostgresPromadRepo {
+    table_name: String,
+    log_sql: bool,
+    server_side_timestamps: bool,
+    attempt_log: bool,
+}
+
+impl Default for PostgresPromadRepo {
+    fn default() -> Self {
+        Self {
+            table_name: DEFAULT_TABLE_NAME.to_string(),
+            log_sql: false,
+            server_side_timestamps: false,
+            attempt_log: false,
+        }
+    }
+}
+
+/// Quote a possibly schema qualified identifier.
+fn quote_ident(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+impl PostgresPromadRepo {
+    /// The tracking table, quoted for use in SQL.
+    fn table(&self) -> String {
+        quote_ident(&self.table_name)
+    }
+
+    /// The attempt log, named after the tracking table and quoted.
+    fn attempts_table(&self) -> String {
+        quote_ident(&format!("{}_attempts", self.table_name))
+    }
+
+    /// Where migrations keep state between attempts, named after the
+    /// tracking table and quoted.
+    fn state_table(&self) -> String {
+        quote_ident(&format!("{}_state", self.table_name))
+    }
+
+    /// Holds the format version of the tables, named after the tracking
+    /// table and quoted.
+    fn meta_table(&self) -> String {
+        quote_ident(&format!("{}_meta", self.table_name))
+    }
+
+    /// The format version the tables were laid out with, failing if that
+    /// was by a newer promad. `None` if no version has been stored yet.
+    async fn check_format(
+        &self,
+        conn: &mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Option<i32>> {
+        let sql = format!(
+            "SELECT format_version FROM {} WHERE id = 1",
+            self.meta_table()
+        );
+        self.log(&sql);
+        let found: Option<i32> = sqlx::query_scalar(&sql).fetch_optional(conn).await?;
+        match found {
+            Some(found) if found > TABLE_FORMAT_VERSION => {
+                Err(crate::error::Error::UnsupportedTableFormat {
+                    found,
+                    supported: TABLE_FORMAT_VERSION,
+                })
+            }
+            found => Ok(found),
+        }
+    }
+
+    fn init_sql(&self) -> Vec<String> {
+        let table = self.table();
+        let unqualified = self.table_name.rsplit('.').next().unwrap_or_default();
+        let index = quote_ident(&format!(
+            "idx_{}_ordering_key",
+            unqualified.trim_start_matches('_')
+        ));
+        let mut sql = vec![
+            format!(
+                r#"CREATE TABLE IF NOT EXISTS {table} (
         name TEXT NOT NULL PRIMARY KEY,
         ordering_key BIGINT NOT NULL,
-        created_at TIMESTAMP WITH TIME ZONE NOT NULL
+        created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+        checksum TEXT,
+        applied_by TEXT
+    );"#
+            ),
+            format!("CREATE INDEX IF NOT EXISTS {index} ON {table} (ordering_key);"),
+            format!(
+                r#"CREATE TABLE IF NOT EXISTS {} (
+        migration TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (migration, key)
+    );"#,
+                self.state_table()
+            ),
+        ];
+        if self.attempt_log {
+            sql.push(format!(
+                r#"CREATE TABLE IF NOT EXISTS {} (
+        id BIGSERIAL PRIMARY KEY,
+        name TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+        finished_at TIMESTAMP WITH TIME ZONE,
+        error TEXT
     );"#,
-    "CREATE INDEX IF NOT EXISTS idx_promad_ordering_key ON _promad (ordering_key);",
-];
+                self.attempts_table()
+            ));
+        }
+        sql
+    }
+
+    /// Bring a tracking table laid out before `TABLE_FORMAT_VERSION` up to
+    /// date. Only run when the stored version is older, since `ALTER TABLE`
+    /// locks the table exclusively and needs its owner even when there's
+    /// nothing to change.
+    fn upgrade_sql(&self) -> Vec<String> {
+        let table = self.table();
+        vec![
+            format!("ALTER TABLE {table} ADD COLUMN IF NOT EXISTS checksum TEXT;"),
+            format!("ALTER TABLE {table} ALTER COLUMN created_at SET DEFAULT now();"),
+            format!("ALTER TABLE {table} ADD COLUMN IF NOT EXISTS applied_by TEXT;"),
+        ]
+    }
 
-pub struct PostgresPromadRepo;
+    fn log(&self, sql: &str) {
+        if self.log_sql {
+            tracing::info!(target: "promad::sql", "{}", sql);
+        }
+    }
+}
 
 #[async_trait]
 impl PromadRepo<Postgres> for PostgresPromadRepo {
     fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    fn set_sql_logging(&mut self, enabled: bool) {
+        self.log_sql = enabled;
+    }
+
+    fn set_server_side_timestamps(&mut self, enabled: bool) {
+        self.server_side_timestamps = enabled;
+    }
+
+    fn set_attempt_log(&mut self, enabled: bool) {
+        self.attempt_log = enabled;
+    }
+
+    fn set_table_name(&mut self, table_name: &str) {
+        self.table_name = table_name.to_string();
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
     }
 
     async fn init<'a>(
         &self,
         conn: &'a mut <Postgres as Database>::Connection,
     ) -> crate::error::Result<()> {
-        for sql in INIT_SQL {
-            sqlx::query(sql).execute(&mut *conn).await?;
+        let sql = format!(
+            r#"CREATE TABLE IF NOT EXISTS {} (
+        id INT PRIMARY KEY CHECK (id = 1),
+        format_version INT NOT NULL
+    );"#,
+            self.meta_table()
+        );
+        self.log(&sql);
+        sqlx::query(&sql).execute(&mut *conn).await?;
+        let found = self.check_format(&mut *conn).await?;
+
+        for sql in self.init_sql() {
+            self.log(&sql);
+            sqlx::query(&sql).execute(&mut *conn).await?;
+        }
+
+        if found == Some(TABLE_FORMAT_VERSION) {
+            return Ok(());
+        }
+        for sql in self.upgrade_sql() {
+            self.log(&sql);
+            sqlx::query(&sql).execute(&mut *conn).await?;
         }
+
+        let sql = format!(
+            "INSERT INTO {} (id, format_version) VALUES (1, $1) ON CONFLICT (id) DO UPDATE SET format_version = EXCLUDED.format_version",
+            self.meta_table()
+        );
+        self.log(&sql);
+        sqlx::query(&sql)
+            .bind(TABLE_FORMAT_VERSION)
+            .execute(conn)
+            .await?;
         Ok(())
     }
 
+    // Only takes effect as the first statement of the transaction.
     async fn set_read_only<'a>(
         &self,
         conn: &'a mut <Postgres as Database>::Connection,
     ) -> crate::error::Result<()> {
-        sqlx::query("SET TRANSACTION READ ONLY")
+        let sql = "SET TRANSACTION READ ONLY";
+        self.log(sql);
+        sqlx::query(sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn lock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        match strategy {
+            LockStrategy::Advisory(key) => {
+                let sql = "SELECT pg_advisory_lock($1)";
+                self.log(sql);
+                sqlx::query(sql).bind(key).execute(conn).await?;
+            }
+            LockStrategy::AdvisoryXact(key) => {
+                let sql = "SELECT pg_advisory_xact_lock($1)";
+                self.log(sql);
+                sqlx::query(sql).bind(key).execute(conn).await?;
+            }
+            LockStrategy::TableLock => {
+                // Conflicts with itself but not with the row locks taken
+                // while recording migrations.
+                let sql = format!("LOCK TABLE {} IN SHARE UPDATE EXCLUSIVE MODE", self.table());
+                self.log(&sql);
+                sqlx::query(&sql).execute(conn).await?;
+            }
+            LockStrategy::None => {}
+        }
+        Ok(())
+    }
+
+    async fn unlock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        if let LockStrategy::Advisory(key) = strategy {
+            let sql = "SELECT pg_advisory_unlock($1)";
+            self.log(sql);
+            sqlx::query(sql).bind(key).execute(conn).await?;
+        }
+        Ok(())
+    }
+
+    async fn start_attempt<'a>(
+        &self,
+        name: &str,
+        direction: Direction,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Option<i64>> {
+        if !self.attempt_log {
+            return Ok(None);
+        }
+        let sql = format!(
+            "INSERT INTO {} (name, direction) VALUES ($1, $2) RETURNING id",
+            self.attempts_table()
+        );
+        self.log(&sql);
+        let (id,): (i64,) = sqlx::query_as(&sql)
+            .bind(name)
+            .bind(direction.as_str())
+            .fetch_one(conn)
+            .await?;
+        Ok(Some(id))
+    }
+
+    async fn finish_attempt<'a>(
+        &self,
+        id: i64,
+        error: Option<&str>,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!(
+            "UPDATE {} SET finished_at = now(), error = $1 WHERE id = $2",
+            self.attempts_table()
+        );
+        self.log(&sql);
+        sqlx::query(&sql).bind(error).bind(id).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn attempts<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Vec<PromadAttempt>> {
+        // The log only exists once it's been turned on.
+        let sql = "SELECT to_regclass($1) IS NOT NULL";
+        self.log(sql);
+        let (exists,): (bool,) = sqlx::query_as(sql)
+            .bind(self.attempts_table())
+            .fetch_one(&mut *conn)
+            .await?;
+        if !exists {
+            return Ok(vec![]);
+        }
+        let sql = format!("SELECT * FROM {} ORDER BY id", self.attempts_table());
+        self.log(&sql);
+        let attempts = sqlx::query_as::<_, PromadAttempt>(&sql)
+            .fetch_all(conn)
+            .await?;
+        Ok(attempts)
+    }
+
+    async fn state_get<'a>(
+        &self,
+        migration: &str,
+        key: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Option<String>> {
+        let sql = format!(
+            "SELECT value FROM {} WHERE migration = $1 AND key = $2",
+            self.state_table()
+        );
+        self.log(&sql);
+        let value = sqlx::query_scalar(&sql)
+            .bind(migration)
+            .bind(key)
+            .fetch_optional(conn)
+            .await?;
+        Ok(value)
+    }
+
+    async fn state_set<'a>(
+        &self,
+        migration: &str,
+        key: &str,
+        value: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!(
+            "INSERT INTO {} (migration, key, value) VALUES ($1, $2, $3) ON CONFLICT (migration, key) DO UPDATE SET value = EXCLUDED.value",
+            self.state_table()
+        );
+        self.log(&sql);
+        sqlx::query(&sql)
+            .bind(migration)
+            .bind(key)
+            .bind(value)
             .execute(conn)
             .await?;
         Ok(())
     }
 
-    async fn get_all<'a>(
+    async fn state_clear<'a>(
         &self,
+        migration: &str,
         conn: &'a mut <Postgres as Database>::Connection,
-    ) -> crate::error::Result<Vec<PromadRow>> {
-        let rows = sqlx::query_as::<_, PromadRow>("SELECT * FROM _promad ORDER BY ordering_key")
+    ) -> crate::error::Result<()> {
+        let sql = format!("DELETE FROM {} WHERE migration = $1", self.state_table());
+        self.log(&sql);
+        sqlx::query(&sql).bind(migration).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn exists<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        let sql = "SELECT to_regclass($1) IS NOT NULL";
+        self.log(sql);
+        let (exists,): (bool,) = sqlx::query_as(sql)
+            .bind(self.table())
+            .fetch_one(conn)
+            .await?;
+        Ok(exists)
+    }
+
+    async fn try_lock<'a>(
+        &self,
+        strategy: &LockStrategy,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<bool> {
+        let acquired = match strategy {
+            LockStrategy::Advisory(key) => {
+                let sql = "SELECT pg_try_advisory_lock($1)";
+                self.log(sql);
+                let (acquired,): (bool,) =
+                    sqlx::query_as(sql).bind(key).fetch_one(&mut *conn).await?;
+                if acquired {
+                    self.unlock(strategy, conn).await?;
+                }
+                acquired
+            }
+            LockStrategy::AdvisoryXact(key) => {
+                // Released when the caller's transaction ends.
+                let sql = "SELECT pg_try_advisory_xact_lock($1)";
+                self.log(sql);
+                let (acquired,): (bool,) = sqlx::query_as(sql).bind(key).fetch_one(conn).await?;
+                acquired
+            }
+            LockStrategy::TableLock => {
+                // Nobody can be holding a lock on a table that doesn't exist yet.
+                if !self.exists(&mut *conn).await? {
+                    return Ok(true);
+                }
+                let sql = format!(
+                    "LOCK TABLE {} IN SHARE UPDATE EXCLUSIVE MODE NOWAIT",
+                    self.table()
+                );
+                self.log(&sql);
+                match sqlx::query(&sql).execute(conn).await {
+                    Ok(_) => true,
+                    // lock_not_available
+                    Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("55P03") => false,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            LockStrategy::None => true,
+        };
+        Ok(acquired)
+    }
+
+    async fn session_id<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Option<i64>> {
+        let sql = "SELECT pg_backend_pid()";
+        self.log(sql);
+        let (pid,): (i32,) = sqlx::query_as(sql).fetch_one(conn).await?;
+        Ok(Some(pid.into()))
+    }
+
+    async fn blocking_sessions<'a>(
+        &self,
+        session_id: i64,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Vec<i64>> {
+        let sql = "SELECT unnest(pg_blocking_pids($1))";
+        self.log(sql);
+        let pids: Vec<(i32,)> = sqlx::query_as(sql)
+            .bind(session_id as i32)
             .fetch_all(conn)
             .await?;
+        Ok(pids.into_iter().map(|(pid,)| pid.into()).collect())
+    }
+
+    async fn schema_snapshot<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Vec<String>> {
+        let sql = "SELECT format('table %s.%s %s', table_schema, table_name, table_type)
+                FROM information_schema.tables
+                WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+            UNION ALL
+            SELECT format('column %s.%s.%s %s nullable=%s default=%s',
+                    table_schema, table_name, column_name, data_type, is_nullable, column_default)
+                FROM information_schema.columns
+                WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+            UNION ALL
+            SELECT format('constraint %s.%s.%s %s',
+                    constraint_schema, table_name, constraint_name, constraint_type)
+                FROM information_schema.table_constraints
+                WHERE constraint_schema NOT IN ('pg_catalog', 'information_schema')
+            UNION ALL
+            SELECT format('index %s.%s %s', schemaname, indexname, indexdef)
+                FROM pg_indexes
+                WHERE schemaname NOT IN ('pg_catalog', 'information_schema')
+            ORDER BY 1";
+        self.log(sql);
+        let lines: Vec<(String,)> = sqlx::query_as(sql).fetch_all(conn).await?;
+        Ok(lines.into_iter().map(|(line,)| line).collect())
+    }
+
+    async fn rows_changed<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<i64> {
+        let sql = "SELECT coalesce(sum(n_tup_ins + n_tup_upd + n_tup_del), 0)::BIGINT
+            FROM pg_stat_xact_all_tables";
+        self.log(sql);
+        let (changed,): (i64,) = sqlx::query_as(sql).fetch_one(conn).await?;
+        Ok(changed)
+    }
+
+    async fn check_ddl<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        for sql in [
+            "CREATE TEMP TABLE _promad_preflight (id INT)",
+            "DROP TABLE _promad_preflight",
+        ] {
+            self.log(sql);
+            sqlx::query(sql).execute(&mut *conn).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_isolation<'a>(
+        &self,
+        level: IsolationLevel,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!("SET TRANSACTION ISOLATION LEVEL {}", level.as_sql());
+        self.log(&sql);
+        sqlx::query(&sql).execute(conn).await?;
+        Ok(())
+    }
+
+    // `SET LOCAL` is undone when the transaction ends, so the connection goes
+    // back to the pool without needing a `RESET ROLE`.
+    async fn set_role<'a>(
+        &self,
+        role: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!("SET LOCAL ROLE {role}");
+        self.log(&sql);
+        sqlx::query(&sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn execute<'a>(
+        &self,
+        sql: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        self.log(sql);
+        sqlx::query(sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn notify<'a>(
+        &self,
+        channel: &str,
+        payload: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        // `pg_notify` takes the channel as a parameter, which `NOTIFY`
+        // can't, and is delivered on commit all the same.
+        let sql = "SELECT pg_notify($1, $2)";
+        self.log(sql);
+        sqlx::query(sql)
+            .bind(channel)
+            .bind(payload)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_all<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<Vec<PromadRow>> {
+        let sql = format!("SELECT * FROM {} ORDER BY ordering_key", self.table());
+        self.log(&sql);
+        let rows = sqlx::query_as::<_, PromadRow>(&sql).fetch_all(conn).await?;
         Ok(rows)
     }
 
@@ -68,7 +587,9 @@ impl PromadRepo<Postgres> for PostgresPromadRepo {
         name: &str,
         conn: &'a mut <Postgres as Database>::Connection,
     ) -> crate::error::Result<Option<PromadRow>> {
-        let row = sqlx::query_as::<_, PromadRow>("SELECT * FROM _promad WHERE name = $1")
+        let sql = format!("SELECT * FROM {} WHERE name = $1", self.table());
+        self.log(&sql);
+        let row = sqlx::query_as::<_, PromadRow>(&sql)
             .bind(name)
             .fetch_optional(conn)
             .await?;
@@ -79,22 +600,129 @@ impl PromadRepo<Postgres> for PostgresPromadRepo {
         &self,
         row: &PromadRow,
         conn: &'a mut <Postgres as Database>::Connection,
-    ) -> crate::error::Result<()> {
-        sqlx::query("INSERT INTO _promad (name, ordering_key, created_at) VALUES ($1, $2, $3)")
+    ) -> crate::error::Result<bool> {
+        if self.server_side_timestamps {
+            let sql = format!(
+                "INSERT INTO {} (name, ordering_key, checksum, applied_by) VALUES ($1, $2, $3, $4) ON CONFLICT (name) DO NOTHING",
+                self.table()
+            );
+            self.log(&sql);
+            let res = sqlx::query(&sql)
+                .bind(row.name.clone())
+                .bind(row.ordering_key)
+                .bind(row.checksum.clone())
+                .bind(row.applied_by.clone())
+                .execute(conn)
+                .await?;
+            return Ok(res.rows_affected() == 1);
+        }
+        let sql = format!(
+            "INSERT INTO {} (name, ordering_key, created_at, checksum, applied_by) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (name) DO NOTHING",
+            self.table()
+        );
+        self.log(&sql);
+        let res = sqlx::query(&sql)
             .bind(row.name.clone())
             .bind(row.ordering_key)
             .bind(row.created_at)
+            .bind(row.checksum.clone())
+            .bind(row.applied_by.clone())
             .execute(conn)
             .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn insert_many<'a>(
+        &self,
+        rows: &[PromadRow],
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut query = if self.server_side_timestamps {
+            let mut query = QueryBuilder::<Postgres>::new(format!(
+                "INSERT INTO {} (name, ordering_key, checksum, applied_by) ",
+                self.table()
+            ));
+            query.push_values(rows, |mut values, row| {
+                values
+                    .push_bind(row.name.clone())
+                    .push_bind(row.ordering_key)
+                    .push_bind(row.checksum.clone())
+                    .push_bind(row.applied_by.clone());
+            });
+            query
+        } else {
+            let mut query = QueryBuilder::<Postgres>::new(format!(
+                "INSERT INTO {} (name, ordering_key, created_at, checksum, applied_by) ",
+                self.table()
+            ));
+            query.push_values(rows, |mut values, row| {
+                values
+                    .push_bind(row.name.clone())
+                    .push_bind(row.ordering_key)
+                    .push_bind(row.created_at)
+                    .push_bind(row.checksum.clone())
+                    .push_bind(row.applied_by.clone());
+            });
+            query
+        };
+        // Like `insert`, a row another process recorded first wins.
+        query.push(" ON CONFLICT (name) DO NOTHING");
+        self.log(query.sql());
+        query.build().execute(conn).await?;
+        Ok(())
+    }
+
+    async fn clear<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!("DELETE FROM {}", self.table());
+        self.log(&sql);
+        sqlx::query(&sql).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn drop_table<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        // The ordering key index goes with the table.
+        let sql = format!(
+            "DROP TABLE IF EXISTS {}, {}, {}, {}",
+            self.table(),
+            self.attempts_table(),
+            self.state_table(),
+            self.meta_table()
+        );
+        self.log(&sql);
+        sqlx::query(&sql).execute(conn).await?;
         Ok(())
     }
 
     async fn delete<'a>(
         &self,
-        name: &'static str,
+        name: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> crate::error::Result<()> {
+        let sql = format!("DELETE FROM {} WHERE name = $1", self.table());
+        self.log(&sql);
+        sqlx::query(&sql).bind(name).execute(conn).await?;
+        Ok(())
+    }
+
+    async fn update_checksum<'a>(
+        &self,
+        name: &str,
+        checksum: Option<&str>,
         conn: &'a mut <Postgres as Database>::Connection,
     ) -> crate::error::Result<()> {
-        sqlx::query("DELETE FROM _promad WHERE name = $1")
+        let sql = format!("UPDATE {} SET checksum = $1 WHERE name = $2", self.table());
+        self.log(&sql);
+        sqlx::query(&sql)
+            .bind(checksum)
             .bind(name)
             .execute(conn)
             .await?;
@@ -0,0 +1,393 @@
+// ┌───────────────────────────────────────────────────────────────────────────┐
+// │                                                                           │
+// │  ██████╗ ██████╗  ██████╗   Copyright (C) The Prospective Company         │
+// │  ██╔══██╗██╔══██╗██╔═══██╗  All Rights Reserved - April 2022              │
+// │  ██████╔╝██████╔╝██║   ██║                                                │
+// │  ██╔═══╝ ██╔══██╗██║   ██║  Proprietary and confidential. Unauthorized    │
+// │  ██║     ██║  ██║╚██████╔╝  copying of this file, via any medium is       │
+// │  ╚═╝     ╚═╝  ╚═╝ ╚═════╝   strictly prohibited.                          │
+// │                                                                           │
+// └───────────────────────────────────────────────────────────────────────────┘
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use sqlx::{database::HasArguments, Database, Executor, IntoArguments};
+
+use crate::{checksum::Checksum, Migration, MigrationCtx, RenderedMigration};
+
+/// Split a string of SQL into its statements on the `;` that end them.
+/// Semicolons inside string literals, quoted identifiers, dollar quoted
+/// bodies and comments don't count. Statements are trimmed, and ones that
+/// are empty or only comments are dropped.
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    // Whether the current statement has anything besides comments in it.
+    let mut has_code = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b';' => {
+                if has_code {
+                    statements.push(sql[start..i].trim());
+                }
+                start = i + 1;
+                has_code = false;
+                i += 1;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = sql[i..].find('\n').map_or(bytes.len(), |end| i + end + 1);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                // Block comments nest in Postgres.
+                let mut depth = 0;
+                while i < bytes.len() {
+                    if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+                        depth += 1;
+                        i += 2;
+                    } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            quote @ (b'\'' | b'"') => {
+                // `E'...'` strings can escape their quote with a backslash.
+                let escapes = quote == b'\''
+                    && i > 0
+                    && matches!(bytes[i - 1], b'E' | b'e')
+                    && (i < 2 || !is_ident_byte(bytes[i - 2]));
+                has_code = true;
+                i += 1;
+                while i < bytes.len() {
+                    if escapes && bytes[i] == b'\\' {
+                        i += 2;
+                    } else if bytes[i] == quote {
+                        // A doubled quote is an escaped quote.
+                        if bytes.get(i + 1) == Some(&quote) {
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            b'$' if i == 0 || !is_ident_byte(bytes[i - 1]) => {
+                has_code = true;
+                match dollar_tag(&sql[i..]) {
+                    Some(tag) => {
+                        let body = i + tag.len();
+                        i = sql[body..]
+                            .find(tag)
+                            .map_or(bytes.len(), |end| body + end + tag.len());
+                    }
+                    // A positional parameter like `$1`.
+                    None => i += 1,
+                }
+            }
+            byte => {
+                if !byte.is_ascii_whitespace() {
+                    has_code = true;
+                }
+                i += 1;
+            }
+        }
+    }
+    if has_code {
+        statements.push(sql[start..].trim());
+    }
+    statements
+}
+
+fn is_ident_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80
+}
+
+/// The `$tag$` opening a dollar quoted string at the start of `sql`, if any.
+fn dollar_tag(sql: &str) -> Option<&str> {
+    let end = sql[1..].find(|c: char| !(c.is_alphanumeric() || c == '_'))? + 1;
+    let tag_name = &sql[1..end];
+    if sql[end..].starts_with('$') && !tag_name.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(&sql[..=end])
+    } else {
+        None
+    }
+}
+
+/// Execute every statement in `sql` one after another on `conn`, since
+/// a single prepared query can only hold one. See `split_statements`.
+pub async fn exec_batch<DB>(
+    conn: &mut <DB as Database>::Connection,
+    sql: &str,
+) -> crate::error::Result<()>
+where
+    DB: Database,
+    for<'c> &'c mut <DB as Database>::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    for statement in split_statements(sql) {
+        sqlx::query(statement).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// A migration written as plain SQL, usually loaded from a pair of
+/// `.up.sql`/`.down.sql` files. Without a down script the migration is
+/// irreversible. The checksum covers the up script.
+pub struct SqlFileMigration {
+    name: Cow<'static, str>,
+    up: Cow<'static, str>,
+    down: Option<Cow<'static, str>>,
+}
+
+impl SqlFileMigration {
+    pub fn new(
+        name: impl Into<Cow<'static, str>>,
+        up: impl Into<Cow<'static, str>>,
+        down: Option<impl Into<Cow<'static, str>>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            up: up.into(),
+            down: down.map(Into::into),
+        }
+    }
+}
+
+#[async_trait]
+impl<DB> Migration<DB> for SqlFileMigration
+where
+    DB: Database,
+    for<'c> &'c mut <DB as Database>::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    fn name(&self) -> Cow<'static, str> {
+        self.name.clone()
+    }
+
+    async fn up(&self, ctx: &mut MigrationCtx<'_, DB>) -> crate::error::Result<()> {
+        exec_batch::<DB>(ctx.write(), &self.up).await
+    }
+
+    async fn down(&self, ctx: &mut MigrationCtx<'_, DB>) -> crate::error::Result<()> {
+        match &self.down {
+            Some(down) => exec_batch::<DB>(ctx.write(), down).await,
+            None => Ok(()),
+        }
+    }
+
+    fn reversible(&self) -> bool {
+        self.down.is_some()
+    }
+
+    fn checksum(&self) -> Option<Checksum> {
+        Some(Checksum::sha256(self.up.as_bytes()))
+    }
+
+    fn render(&self) -> Option<RenderedMigration> {
+        Some(RenderedMigration {
+            up_sql: self.up.to_string(),
+            down_sql: self.down.as_ref().map(|x| x.to_string()),
+        })
+    }
+}
+
+/// Somewhere SQL migrations are loaded from, such as a directory on disk,
+/// one embedded in the binary, or object storage. Implement it to load
+/// migrations from a store promad doesn't know about.
+#[async_trait]
+pub trait MigrationSource: Send + Sync {
+    /// Load every migration, in the order they should be applied.
+    async fn load(&self) -> crate::error::Result<Vec<SqlFileMigration>>;
+}
+
+/// The `.up.sql`/`.down.sql` pairs anywhere under a directory on disk,
+/// ordered by the number their file names start with.
+pub struct FsSource {
+    dir: PathBuf,
+}
+
+impl FsSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl MigrationSource for FsSource {
+    async fn load(&self) -> crate::error::Result<Vec<SqlFileMigration>> {
+        dir_migrations(&self.dir)
+    }
+}
+
+/// The `.up.sql`/`.down.sql` pairs anywhere under a directory embedded
+/// with `include_dir!`, laid out like `FsSource`.
+#[cfg(feature = "include_dir")]
+pub struct EmbeddedSource {
+    dir: &'static include_dir::Dir<'static>,
+}
+
+#[cfg(feature = "include_dir")]
+impl EmbeddedSource {
+    pub fn new(dir: &'static include_dir::Dir<'static>) -> Self {
+        Self { dir }
+    }
+}
+
+#[cfg(feature = "include_dir")]
+#[async_trait]
+impl MigrationSource for EmbeddedSource {
+    async fn load(&self) -> crate::error::Result<Vec<SqlFileMigration>> {
+        embedded_migrations(self.dir)
+    }
+}
+
+/// A migration script found while loading a directory.
+struct Script {
+    /// Where the script was found, for errors.
+    path: String,
+    file_name: String,
+    sql: Cow<'static, str>,
+}
+
+/// Pair up the `.up.sql`/`.down.sql` scripts into migrations, ordered by
+/// the number their file names start with.
+fn pair_scripts(files: Vec<Script>) -> crate::error::Result<Vec<SqlFileMigration>> {
+    type Scripts = (Option<Cow<'static, str>>, Option<Cow<'static, str>>);
+    let mut scripts = BTreeMap::<String, Scripts>::new();
+    for file in files {
+        let (name, is_up) = if let Some(name) = file.file_name.strip_suffix(".up.sql") {
+            (name, true)
+        } else if let Some(name) = file.file_name.strip_suffix(".down.sql") {
+            (name, false)
+        } else {
+            continue;
+        };
+        let (up, down) = scripts.entry(name.to_string()).or_default();
+        let script = if is_up { up } else { down };
+        if script.replace(file.sql).is_some() {
+            return Err(crate::error::Error::InvalidMigrationFile {
+                path: file.path,
+                reason: "another file has the same name".to_string(),
+            });
+        }
+    }
+
+    let mut migrations = scripts
+        .into_iter()
+        .map(|(name, (up, down))| match up {
+            Some(up) => Ok(SqlFileMigration::new(name, up, down)),
+            None => Err(crate::error::Error::InvalidMigrationFile {
+                path: format!("{name}.down.sql"),
+                reason: "there's no matching .up.sql".to_string(),
+            }),
+        })
+        .collect::<crate::error::Result<Vec<_>>>()?;
+    // Unnumbered migrations go last.
+    migrations
+        .sort_by_cached_key(|x| (numeric_prefix(&x.name).unwrap_or(i64::MAX), x.name.clone()));
+    Ok(migrations)
+}
+
+/// Load the `.up.sql`/`.down.sql` pairs anywhere under an embedded
+/// directory, ordered by the number their file names start with.
+/// Other files are ignored.
+#[cfg(feature = "include_dir")]
+pub(crate) fn embedded_migrations(
+    dir: &include_dir::Dir<'static>,
+) -> crate::error::Result<Vec<SqlFileMigration>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir];
+    while let Some(dir) = dirs.pop() {
+        // `entries` rather than `files` keeps the `'static` lifetime.
+        for entry in dir.entries() {
+            let file = match entry {
+                include_dir::DirEntry::Dir(dir) => {
+                    dirs.push(dir);
+                    continue;
+                }
+                include_dir::DirEntry::File(file) => file,
+            };
+            let path = file.path();
+            let Some(file_name) = path.file_name().and_then(|x| x.to_str()) else {
+                continue;
+            };
+            if !is_script(file_name) {
+                continue;
+            }
+            let sql =
+                file.contents_utf8()
+                    .ok_or_else(|| crate::error::Error::InvalidMigrationFile {
+                        path: path.display().to_string(),
+                        reason: "not valid UTF-8".to_string(),
+                    })?;
+            files.push(Script {
+                path: path.display().to_string(),
+                file_name: file_name.to_string(),
+                sql: sql.into(),
+            });
+        }
+    }
+    pair_scripts(files)
+}
+
+/// Load the `.up.sql`/`.down.sql` pairs anywhere under a directory on
+/// disk, like `embedded_migrations`.
+pub(crate) fn dir_migrations(dir: &Path) -> crate::error::Result<Vec<SqlFileMigration>> {
+    let unreadable = |path: &Path, e: std::io::Error| crate::error::Error::InvalidMigrationFile {
+        path: path.display().to_string(),
+        reason: format!("can't read it: {e}"),
+    };
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).map_err(|e| unreadable(&dir, e))? {
+            let path = entry.map_err(|e| unreadable(&dir, e))?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let Some(file_name) = path.file_name().and_then(|x| x.to_str()) else {
+                continue;
+            };
+            if !is_script(file_name) {
+                continue;
+            }
+            let sql = std::fs::read_to_string(&path).map_err(|e| unreadable(&path, e))?;
+            files.push(Script {
+                path: path.display().to_string(),
+                file_name: file_name.to_string(),
+                sql: sql.into(),
+            });
+        }
+    }
+    pair_scripts(files)
+}
+
+/// Whether a file is an up or down script.
+fn is_script(file_name: &str) -> bool {
+    file_name.ends_with(".up.sql") || file_name.ends_with(".down.sql")
+}
+
+/// The number a file name starts with, so `10_users` sorts after `2_posts`.
+fn numeric_prefix(name: &str) -> Option<i64> {
+    let end = name
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(name.len());
+    name[..end].parse().ok()
+}
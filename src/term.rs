@@ -0,0 +1,120 @@
+// ┌───────────────────────────────────────────────────────────────────────────┐
+// │                                                                           │
+// │  ██████╗ ██████╗  ██████╗   Copyright (C) The Prospective Company         │
+// │  ██╔══██╗██╔══██╗██╔═══██╗  All Rights Reserved - April 2022              │
+// │  ██████╔╝██████╔╝██║   ██║                                                │
+// │  ██╔═══╝ ██╔══██╗██║   ██║  Proprietary and confidential. Unauthorized    │
+// │  ██║     ██║  ██║╚██████╔╝  copying of this file, via any medium is       │
+// │  ╚═╝     ╚═╝  ╚═╝ ╚═════╝   strictly prohibited.                          │
+// │                                                                           │
+// └───────────────────────────────────────────────────────────────────────────┘
+
+//! What the terminal can show. Colours and symbols outside ASCII are
+//! turned off when `NO_COLOR` is set or the locale isn't UTF-8.
+
+use once_cell::sync::Lazy;
+
+/// Symbols printed next to migrations and checks.
+pub(crate) struct Symbols {
+    /// Succeeded, or applied.
+    pub ok: &'static str,
+    /// Not applied.
+    pub no: &'static str,
+    /// Failed.
+    pub fail: &'static str,
+    /// Not known.
+    pub unknown: &'static str,
+    /// Starts the summary after a run, including its trailing space.
+    pub done: &'static str,
+    /// Spinner frames, the last of which is shown once it stops.
+    pub ticks: &'static str,
+}
+
+const UNICODE: Symbols = Symbols {
+    ok: "✓",
+    no: "✗",
+    fail: "✗",
+    unknown: "?",
+    done: "✨ ",
+    ticks: "◐◓◑◒ ",
+};
+
+const ASCII: Symbols = Symbols {
+    ok: "[x]",
+    no: "[ ]",
+    fail: "[!]",
+    unknown: "[?]",
+    done: "",
+    ticks: "|/-\\ ",
+};
+
+/// Decided once per process. Turns `colored` off too, since it only looks
+/// at `NO_COLOR` and not the locale.
+static PLAIN: Lazy<bool> = Lazy::new(|| {
+    let var = |key: &str| std::env::var_os(key).map(|x| x.to_string_lossy().into_owned());
+    let plain = no_color(var("NO_COLOR").as_deref())
+        || !utf8_locale(["LC_ALL", "LC_CTYPE", "LANG"].map(var));
+    if plain {
+        colored::control::set_override(false);
+    }
+    plain
+});
+
+/// The symbols the terminal can show. Call this before colouring anything
+/// so that colours are off in plain mode.
+pub(crate) fn symbols() -> &'static Symbols {
+    if *PLAIN {
+        &ASCII
+    } else {
+        &UNICODE
+    }
+}
+
+/// Whether `NO_COLOR` with this value turns colours off. See
+/// <https://no-color.org>.
+fn no_color(value: Option<&str>) -> bool {
+    value.is_some_and(|x| !x.is_empty())
+}
+
+/// Whether the locale is UTF-8, going by the first of the values of
+/// `LC_ALL`, `LC_CTYPE` and `LANG` that's set. Without any of them UTF-8 is
+/// assumed.
+fn utf8_locale(values: [Option<String>; 3]) -> bool {
+    let locale = values.into_iter().flatten().find(|x| !x.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(lc_all: Option<&str>, lang: Option<&str>) -> bool {
+        utf8_locale([lc_all.map(Into::into), None, lang.map(Into::into)])
+    }
+
+    #[test]
+    fn test_utf8_locale() {
+        assert!(!locale(None, Some("C")));
+        assert!(locale(None, Some("en_US.UTF-8")));
+        assert!(locale(None, Some("en_US.utf8")));
+        assert!(locale(None, None));
+        // LC_ALL overrides LANG, unless it's empty.
+        assert!(!locale(Some("C"), Some("en_US.UTF-8")));
+        assert!(locale(Some("en_US.UTF-8"), Some("C")));
+        assert!(locale(Some(""), Some("en_US.UTF-8")));
+    }
+
+    #[test]
+    fn test_no_color() {
+        assert!(!no_color(None));
+        // An empty NO_COLOR doesn't count.
+        assert!(!no_color(Some("")));
+        assert!(no_color(Some("1")));
+    }
+}
@@ -1,7 +1,14 @@
-use std::{cell::RefCell, error::Error, sync::Arc};
+#![allow(dead_code)]
+
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
 
 use once_cell::sync::Lazy;
-use promad::{repo::postgres::PostgresPromadRepo, Migration, MigrationUI, Migrator};
+use promad::{
+    repo::postgres::PostgresPromadRepo, MigrationUI, Migrator, ProgressReporter, UiFactory,
+};
 use sqlx::{postgres::PgPoolOptions, PgPool, Postgres};
 use testcontainers::{clients, Container};
 
@@ -16,29 +23,27 @@ macro_rules! create_migration {
 
         #[async_trait::async_trait]
         impl Migration<sqlx::Postgres> for $name {
-            fn name(&self) -> &'static str {
+            fn name(&self) -> std::borrow::Cow<'static, str> {
                 $name_str.into()
             }
 
             async fn up(
                 &self,
-                _read: &mut <sqlx::Postgres as Database>::Connection,
-                write: &mut <sqlx::Postgres as Database>::Connection,
+                ctx: &mut promad::MigrationCtx<'_, sqlx::Postgres>,
             ) -> crate::error::Result<()> {
                 tracing::info!("Running up migration {}", self.name());
                 tracing::info!("Running SQL: {}", $up_sql);
-                sqlx::query($up_sql).execute(write).await?;
+                promad::exec_batch::<sqlx::Postgres>(ctx.write(), $up_sql).await?;
                 Ok(())
             }
 
             async fn down(
                 &self,
-                _read: &mut <sqlx::Postgres as Database>::Connection,
-                write: &mut <sqlx::Postgres as Database>::Connection,
+                ctx: &mut promad::MigrationCtx<'_, sqlx::Postgres>,
             ) -> crate::error::Result<()> {
                 tracing::info!("Running down migration {}", self.name());
                 tracing::info!("Running SQL: {}", $down_sql);
-                sqlx::query($down_sql).execute(write).await?;
+                promad::exec_batch::<sqlx::Postgres>(ctx.write(), $down_sql).await?;
                 Ok(())
             }
         }
@@ -52,14 +57,14 @@ static DOCKER: Lazy<clients::Cli> = Lazy::new(|| clients::Cli::default());
 pub struct TestHarness<'a> {
     pub pool: PgPool,
     pub pgsql: Container<'a, PostgresImage>,
-    pub uis: Arc<RefCell<Vec<MockUI>>>,
+    pub uis: Arc<Mutex<Vec<MockUI>>>,
     pub migrator: Migrator<Postgres>,
     pub repo: PostgresPromadRepo,
 }
 
 impl TestHarness<'_> {
     pub fn get_mock_uis(&self) -> Vec<MockUI> {
-        self.uis.borrow().clone()
+        self.uis.lock().unwrap().clone()
     }
 }
 
@@ -73,23 +78,22 @@ pub async fn make_test_harness() -> Result<TestHarness<'static>, Box<dyn Error>>
             port
         ))
         .await?;
-    let uis = Arc::new(RefCell::new(Vec::new()));
+    let uis = Arc::new(Mutex::new(Vec::new()));
     let uis_clone = uis.clone();
-    let factory: Box<dyn Fn(&[(i64, &dyn Migration<Postgres>)]) -> Box<dyn MigrationUI>> =
-        Box::new(move |_migrations| {
-            let ui = MockUI {
-                messages: Arc::new(RefCell::new(Vec::new())),
-            };
-            uis_clone.clone().borrow_mut().push(ui.clone());
-            Box::new(ui)
-        });
+    let factory: UiFactory<Postgres> = Box::new(move |_migrations| {
+        let ui = MockUI {
+            messages: Arc::new(Mutex::new(Vec::new())),
+        };
+        uis_clone.clone().lock().unwrap().push(ui.clone());
+        Box::new(ui)
+    });
     let migrator = Migrator::create_with_ui(pool.clone(), factory);
     Ok(TestHarness {
         pool,
         pgsql,
         migrator,
         uis,
-        repo: PostgresPromadRepo,
+        repo: PostgresPromadRepo::default(),
     })
 }
 
@@ -97,32 +101,78 @@ pub async fn make_test_harness() -> Result<TestHarness<'static>, Box<dyn Error>>
 pub enum MockUICommands {
     Start(usize, promad::Direction),
     Finish(usize),
-    Complete,
+    Fail(usize),
+    Complete(usize, promad::Direction),
+    SetLength(usize, u64),
+    Inc(usize, u64),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Default, Clone)]
 pub struct MockUI {
-    messages: Arc<RefCell<Vec<MockUICommands>>>,
+    messages: Arc<Mutex<Vec<MockUICommands>>>,
 }
 
 impl MockUI {
     pub fn messages(&self) -> Vec<MockUICommands> {
-        self.messages.borrow().clone()
+        self.messages.lock().unwrap().clone()
     }
 }
 
 impl MigrationUI for MockUI {
     fn start(&self, idx: usize, direction: &promad::Direction) {
         self.messages
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .push(MockUICommands::Start(idx, direction.clone()));
     }
 
-    fn complete(&self) {
-        self.messages.borrow_mut().push(MockUICommands::Complete);
+    fn complete(&self, summary: &promad::RunSummary) {
+        self.messages
+            .lock()
+            .unwrap()
+            .push(MockUICommands::Complete(summary.count, summary.direction));
+    }
+
+    fn fail(&self, idx: usize, _err: &promad::error::Error) {
+        self.messages
+            .lock()
+            .unwrap()
+            .push(MockUICommands::Fail(idx));
     }
 
     fn finish(&self, idx: usize) {
-        self.messages.borrow_mut().push(MockUICommands::Finish(idx));
+        self.messages
+            .lock()
+            .unwrap()
+            .push(MockUICommands::Finish(idx));
+    }
+
+    fn progress(&self, idx: usize) -> Box<dyn ProgressReporter> {
+        Box::new(MockProgress {
+            idx,
+            messages: self.messages.clone(),
+        })
+    }
+}
+
+/// Records what a migration reports into its `MockUI`'s messages.
+struct MockProgress {
+    idx: usize,
+    messages: Arc<Mutex<Vec<MockUICommands>>>,
+}
+
+impl ProgressReporter for MockProgress {
+    fn set_length(&self, len: u64) {
+        self.messages
+            .lock()
+            .unwrap()
+            .push(MockUICommands::SetLength(self.idx, len));
+    }
+
+    fn inc(&self, delta: u64) {
+        self.messages
+            .lock()
+            .unwrap()
+            .push(MockUICommands::Inc(self.idx, delta));
     }
 }
@@ -0,0 +1,569 @@
+#![cfg(all(feature = "any", feature = "sqlite"))]
+
+use std::error::Error;
+
+use promad::{
+    repo::{PromadRepo, PromadRow},
+    Migration, MigrationCtx, Migrator, PlainMigrationUI, SqlFileMigration,
+};
+use sqlx::AnyConnection;
+
+async fn sqlite_migrator(dir: &tempfile::TempDir) -> Result<Migrator<sqlx::Any>, Box<dyn Error>> {
+    sqlx::any::install_default_drivers();
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+    let pool = sqlx::AnyPool::connect(&url).await?;
+    Ok(Migrator::create_with_ui(
+        pool,
+        Box::new(|migrations| Box::new(PlainMigrationUI::new(migrations))),
+    ))
+}
+
+#[tokio::test]
+async fn test_any_sqlite() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut migrator = sqlite_migrator(&dir).await?;
+    migrator.add_migration(Box::new(SqlFileMigration::new(
+        "migration1",
+        "CREATE TABLE test1 (id INTEGER)",
+        Some("DROP TABLE test1"),
+    )))?;
+    migrator.add_migration(Box::new(SqlFileMigration::new(
+        "migration2",
+        "CREATE TABLE test2 (id INTEGER)",
+        Some("DROP TABLE test2"),
+    )))?;
+    assert!(!migrator.is_applied("migration1").await?);
+
+    migrator.apply_all().await?;
+    let listed = migrator.list_migrations().await?;
+    assert!(listed.iter().all(|x| x.run_at().is_some()));
+    assert!(migrator.is_applied("migration2").await?);
+
+    migrator.revert_n(1).await?;
+    assert!(migrator.is_applied("migration1").await?);
+    assert!(!migrator.is_applied("migration2").await?);
+    assert_eq!(migrator.pending_count().await?, 1);
+
+    // Checksums are recorded, so an edited migration is caught.
+    migrator.remove_all_migrations();
+    migrator.add_migration(Box::new(SqlFileMigration::new(
+        "migration1",
+        "CREATE TABLE test1 (id BIGINT)",
+        Some("DROP TABLE test1"),
+    )))?;
+    assert!(matches!(
+        migrator.apply_all().await,
+        Err(promad::error::Error::ChecksumMismatch { .. })
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_any_migrator_sqlite() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+    assert!(matches!(
+        promad::cli::AnyMigrator::connect(&url).await?,
+        promad::cli::AnyMigrator::Any(_)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_any_table_format() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let migrator = sqlite_migrator(&dir).await?;
+    migrator.apply_all().await?;
+
+    let url = format!("sqlite://{}", dir.path().join("test.db").display());
+    let pool = sqlx::AnyPool::connect(&url).await?;
+    sqlx::query("UPDATE _promad_meta SET format_version = format_version + 1")
+        .execute(&pool)
+        .await?;
+    assert!(matches!(
+        migrator.apply_all().await,
+        Err(promad::error::Error::UnsupportedTableFormat { .. })
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sql_logging_leaves_pool_alone() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    sqlx::any::install_default_drivers();
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+    let pool = sqlx::AnyPool::connect(&url).await?;
+    let level = || pool.connect_options().log_settings.statements_level;
+    let original = level();
+
+    let mut migrator = Migrator::create(pool.clone()).with_sql_logging(true);
+    migrator.add_migration(Box::new(SqlFileMigration::new(
+        "migration1",
+        "CREATE TABLE test1 (id INTEGER)",
+        Some("DROP TABLE test1"),
+    )))?;
+    migrator.apply_all().await?;
+    assert_eq!(level(), original);
+
+    // The logging copy of the pool reaches the same database.
+    sqlx::query("SELECT * FROM test1").fetch_all(&pool).await?;
+
+    // Turning it off again migrates through the caller's pool.
+    let migrator = migrator.with_sql_logging(false);
+    migrator.revert_all().await?;
+    assert_eq!(level(), original);
+    assert_eq!(migrator.pending_count().await?, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_table_name() -> Result<(), Box<dyn Error>> {
+    use clap::Parser;
+
+    let dir = tempfile::tempdir()?;
+    let mut migrator = sqlite_migrator(&dir).await?;
+    migrator.add_migration(Box::new(SqlFileMigration::new(
+        "migration1",
+        "CREATE TABLE test1 (id INTEGER)",
+        Some("DROP TABLE test1"),
+    )))?;
+    let cli = promad::cli::PromadCli::parse_from(["promad", "--table-name", "custom", "apply"]);
+    cli.run(migrator).await?;
+
+    // The flag applies to a migrator the caller built.
+    let url = format!("sqlite://{}", dir.path().join("test.db").display());
+    let pool = sqlx::AnyPool::connect(&url).await?;
+    let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM custom")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(count, 1);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn test_cli_export_import() -> Result<(), Box<dyn Error>> {
+    use clap::Parser;
+    use promad::cli::PromadCli;
+
+    let add = |migrator: &mut Migrator<sqlx::Any>| {
+        migrator.add_migration(Box::new(SqlFileMigration::new(
+            "migration1",
+            "CREATE TABLE test1 (id INTEGER)",
+            Some("DROP TABLE test1"),
+        )))
+    };
+    let source = tempfile::tempdir()?;
+    let mut migrator = sqlite_migrator(&source).await?;
+    add(&mut migrator)?;
+    migrator.apply_all().await?;
+    let file = source.path().join("history.json");
+    let path = file.to_str().unwrap();
+    PromadCli::parse_from(["promad", "export", path])
+        .run(migrator)
+        .await?;
+
+    // A dry run imports nothing.
+    let target = tempfile::tempdir()?;
+    let mut migrator = sqlite_migrator(&target).await?.with_dry_run(true);
+    add(&mut migrator)?;
+    let rows: Vec<promad::repo::PromadRow> =
+        serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+    migrator.import_history(&rows, false).await?;
+    assert_eq!(migrator.pending_count().await?, 1);
+
+    let mut migrator = sqlite_migrator(&target).await?;
+    add(&mut migrator)?;
+    PromadCli::parse_from(["promad", "import", path])
+        .run(migrator)
+        .await?;
+    let mut migrator = sqlite_migrator(&target).await?;
+    add(&mut migrator)?;
+    assert_eq!(migrator.pending_count().await?, 0);
+
+    // Importing over a history needs --force.
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "import", path])
+            .run(migrator)
+            .await,
+        Err(promad::error::Error::HistoryNotEmpty(1))
+    ));
+    let mut migrator = sqlite_migrator(&target).await?;
+    add(&mut migrator)?;
+    PromadCli::parse_from(["promad", "import", path, "--force"])
+        .run(migrator)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dry_run() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let add = |migrator: &mut Migrator<sqlx::Any>| {
+        migrator.add_migration(Box::new(SqlFileMigration::new(
+            "create_test",
+            "CREATE TABLE test (id INTEGER)",
+            Some("DROP TABLE test"),
+        )))
+    };
+    let mut migrator = sqlite_migrator(&dir).await?.with_dry_run(true);
+    add(&mut migrator)?;
+    migrator.apply_all().await?;
+
+    // Neither the table nor the history row made it.
+    let mut migrator = sqlite_migrator(&dir).await?;
+    add(&mut migrator)?;
+    assert_eq!(migrator.pending_count().await?, 1);
+    let pool = sqlx::AnyPool::connect(&format!(
+        "sqlite://{}",
+        dir.path().join("test.db").display()
+    ))
+    .await?;
+    let tables: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE name = 'test'")
+            .fetch_all(&pool)
+            .await?;
+    assert!(tables.is_empty());
+
+    // A dry revert leaves what was applied for real in place.
+    migrator.apply_all().await?;
+    let mut migrator = sqlite_migrator(&dir).await?.with_dry_run(true);
+    add(&mut migrator)?;
+    migrator.revert_all().await?;
+    assert_eq!(migrator.pending_count().await?, 0);
+    let tables: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE name = 'test'")
+            .fetch_all(&pool)
+            .await?;
+    assert_eq!(tables.len(), 1);
+
+    // Resetting drops nothing either.
+    migrator.reset(true).await?;
+    let mut migrator = sqlite_migrator(&dir).await?;
+    add(&mut migrator)?;
+    assert_eq!(migrator.pending_count().await?, 0);
+    let tables: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE name IN ('test', '_promad')")
+            .fetch_all(&pool)
+            .await?;
+    assert_eq!(tables.len(), 2);
+
+    // Restamping doesn't store the checksums it would replace.
+    sqlx::query("UPDATE _promad SET checksum = 'md5:00'")
+        .execute(&pool)
+        .await?;
+    let mut migrator = sqlite_migrator(&dir).await?.with_dry_run(true);
+    add(&mut migrator)?;
+    migrator.restamp().await?;
+    let (unstamped,): (i64,) =
+        sqlx::query_as("SELECT count(*) FROM _promad WHERE checksum = 'md5:00'")
+            .fetch_one(&pool)
+            .await?;
+    assert_eq!(unstamped, 1);
+
+    // Nor does squashing rewrite the history.
+    let mut migrator = sqlite_migrator(&dir).await?.with_dry_run(true);
+    migrator.add_migration(Box::new(SqlFileMigration::new(
+        "baseline",
+        "CREATE TABLE test (id INTEGER)",
+        Some("DROP TABLE test"),
+    )))?;
+    migrator.squash("create_test").await?;
+    let names: Vec<(String,)> = sqlx::query_as("SELECT name FROM _promad")
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(names, vec![("create_test".to_string(),)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pending_count_single_connection() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let add = |migrator: &mut Migrator<sqlx::Any>| {
+        migrator.add_migration(Box::new(SqlFileMigration::new(
+            "create_test",
+            "CREATE TABLE test (id INTEGER)",
+            Some("DROP TABLE test"),
+        )))
+    };
+    let mut migrator = sqlite_migrator(&dir).await?;
+    add(&mut migrator)?;
+    migrator.apply_all().await?;
+
+    // A readiness probe's pool of one is enough to count what's pending.
+    let url = format!("sqlite://{}", dir.path().join("test.db").display());
+    let pool = sqlx::any::AnyPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(std::time::Duration::from_secs(5))
+        .connect(&url)
+        .await?;
+    let mut probe = Migrator::create_with_ui(
+        pool,
+        Box::new(|migrations| Box::new(PlainMigrationUI::new(migrations))),
+    );
+    add(&mut probe)?;
+    assert_eq!(probe.pending_count().await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_is_applied_by_another_process() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let migration = || {
+        Box::new(SqlFileMigration::new(
+            "create_test",
+            "CREATE TABLE test (id INTEGER)",
+            Some("DROP TABLE test"),
+        ))
+    };
+    let mut watcher = sqlite_migrator(&dir).await?;
+    watcher.add_migration(migration())?;
+    // Loads the history into the watcher's cache.
+    watcher.list_migrations().await?;
+
+    let mut other = sqlite_migrator(&dir).await?;
+    other.add_migration(migration())?;
+    other.apply_all().await?;
+    assert!(watcher.is_applied("create_test").await?);
+    Ok(())
+}
+
+/// Keeps the history in memory, in a table whose names hold 5 characters.
+struct ShortNames(promad::repo::memory::MemoryPromadRepo<sqlx::Any>);
+
+#[async_trait::async_trait]
+impl PromadRepo<sqlx::Any> for ShortNames {
+    fn new() -> Self {
+        Self(promad::repo::memory::MemoryPromadRepo::new())
+    }
+
+    async fn init<'a>(&self, conn: &'a mut AnyConnection) -> promad::error::Result<()> {
+        self.0.init(conn).await
+    }
+
+    async fn set_read_only<'a>(&self, conn: &'a mut AnyConnection) -> promad::error::Result<()> {
+        self.0.set_read_only(conn).await
+    }
+
+    async fn max_name_length<'a>(
+        &self,
+        _conn: &'a mut AnyConnection,
+    ) -> promad::error::Result<Option<usize>> {
+        Ok(Some(5))
+    }
+
+    async fn get_all<'a>(
+        &self,
+        conn: &'a mut AnyConnection,
+    ) -> promad::error::Result<Vec<PromadRow>> {
+        self.0.get_all(conn).await
+    }
+
+    async fn get<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut AnyConnection,
+    ) -> promad::error::Result<Option<PromadRow>> {
+        self.0.get(name, conn).await
+    }
+
+    async fn insert<'a>(
+        &self,
+        row: &PromadRow,
+        conn: &'a mut AnyConnection,
+    ) -> promad::error::Result<bool> {
+        self.0.insert(row, conn).await
+    }
+
+    async fn clear<'a>(&self, conn: &'a mut AnyConnection) -> promad::error::Result<()> {
+        self.0.clear(conn).await
+    }
+
+    async fn delete<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut AnyConnection,
+    ) -> promad::error::Result<()> {
+        self.0.delete(name, conn).await
+    }
+
+    async fn update_checksum<'a>(
+        &self,
+        name: &str,
+        checksum: Option<&str>,
+        conn: &'a mut AnyConnection,
+    ) -> promad::error::Result<()> {
+        self.0.update_checksum(name, checksum, conn).await
+    }
+}
+
+#[tokio::test]
+async fn test_name_length() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    sqlx::any::install_default_drivers();
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+    let pool = sqlx::AnyPool::connect(&url).await?;
+    let mut migrator = Migrator::create_with_repo(
+        pool,
+        Box::new(ShortNames::new()),
+        Box::new(|_| Box::new(promad::NullMigrationUI)),
+    );
+    let sql =
+        |name: &'static str| Box::new(SqlFileMigration::new(name, "SELECT 1", Some("SELECT 1")));
+    migrator.add_migration(sql("short"))?;
+    migrator.apply_all().await?;
+
+    // Adding works, since the limit is the database's, but nothing runs.
+    migrator.add_migration(sql("longer"))?;
+    assert!(matches!(
+        migrator.apply_all().await,
+        Err(promad::error::Error::MigrationNameTooLong { name, max: 5 }) if name == "longer"
+    ));
+    assert_eq!(migrator.pending_count().await?, 1);
+    Ok(())
+}
+
+struct Tagged(&'static str, &'static [&'static str]);
+
+#[async_trait::async_trait]
+impl Migration<sqlx::Any> for Tagged {
+    fn name(&self) -> std::borrow::Cow<'static, str> {
+        self.0.into()
+    }
+
+    async fn up(&self, ctx: &mut MigrationCtx<'_, sqlx::Any>) -> promad::error::Result<()> {
+        sqlx::query(&format!("CREATE TABLE {} (id INTEGER)", self.0))
+            .execute(ctx.write())
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, ctx: &mut MigrationCtx<'_, sqlx::Any>) -> promad::error::Result<()> {
+        sqlx::query(&format!("DROP TABLE {}", self.0))
+            .execute(ctx.write())
+            .await?;
+        Ok(())
+    }
+
+    fn tags(&self) -> &[&'static str] {
+        self.1
+    }
+}
+
+#[tokio::test]
+async fn test_tag_filters_share_table() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    sqlx::any::install_default_drivers();
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+    let pool = sqlx::AnyPool::connect(&url).await?;
+    let repo = promad::repo::memory::MemoryPromadRepo::<sqlx::Any>::new();
+    let migrator = |tag: &str| -> Result<Migrator<sqlx::Any>, Box<dyn Error>> {
+        let mut migrator = Migrator::create_with_repo(
+            pool.clone(),
+            Box::new(repo.clone()),
+            Box::new(|_| Box::new(promad::NullMigrationUI)),
+        )
+        .with_lock_strategy(promad::LockStrategy::None)
+        .with_tag_filter(tag);
+        migrator.add_migrations(vec![
+            Box::new(Tagged("shared", &[])) as Box<dyn Migration<sqlx::Any>>,
+            Box::new(Tagged("billing", &["billing"])),
+            Box::new(Tagged("search", &["search"])),
+        ])?;
+        Ok(migrator)
+    };
+    let billing = migrator("billing")?;
+    let search = migrator("search")?;
+    billing.apply_all().await?;
+    search.apply_all().await?;
+
+    // Each migration keeps the key of its position in the full list, so
+    // neither filter's rows replace the other's.
+    let mut conn = pool.acquire().await?;
+    let rows = repo
+        .get_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|x| (x.name, x.ordering_key))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        [
+            ("shared".to_string(), 0),
+            ("billing".to_string(), 1),
+            ("search".to_string(), 2)
+        ]
+    );
+
+    // Running again would fail creating the tables a second time.
+    assert_eq!(billing.pending_count().await?, 0);
+    billing.apply_all().await?;
+    search.apply_all().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_case_insensitive_history() -> Result<(), Box<dyn Error>> {
+    use clap::Parser;
+
+    let dir = tempfile::tempdir()?;
+    sqlx::any::install_default_drivers();
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+    let pool = sqlx::AnyPool::connect(&url).await?;
+    let repo = promad::repo::memory::MemoryPromadRepo::<sqlx::Any>::new();
+    let mut conn = pool.acquire().await?;
+    // Recorded by a database whose collation ignores case.
+    repo.insert(
+        &PromadRow {
+            name: " Foo".to_string(),
+            ordering_key: 0,
+            created_at: chrono::Utc::now(),
+            checksum: None,
+            applied_by: None,
+        },
+        &mut conn,
+    )
+    .await?;
+
+    let mut migrator = Migrator::create_with_repo(
+        pool.clone(),
+        Box::new(repo.clone()),
+        Box::new(|_| Box::new(promad::NullMigrationUI)),
+    )
+    .with_lock_strategy(promad::LockStrategy::None)
+    .with_case_insensitive_names(true);
+    migrator.add_migration(Box::new(Tagged("foo", &[])))?;
+    assert!(migrator.orphaned().await?.is_empty());
+    assert_eq!(migrator.pending_count().await?, 0);
+    assert!(migrator.is_applied("FOO").await?);
+    // Creating the table again would fail.
+    migrator.apply_all().await?;
+
+    // Groups are matched the same way, from the library and the CLI.
+    migrator.add_migration(Box::new(Tagged("bar", &[])))?;
+    migrator.apply_group(&[" BAR"]).await?;
+    assert!(migrator.is_applied("bar").await?);
+    migrator.add_migration(Box::new(Tagged("baz", &[])))?;
+    // The CLI closes the pool it's given when it's done.
+    let mut cli_migrator = Migrator::create_with_repo(
+        sqlx::AnyPool::connect(&url).await?,
+        Box::new(repo.clone()),
+        Box::new(|_| Box::new(promad::NullMigrationUI)),
+    )
+    .with_lock_strategy(promad::LockStrategy::None)
+    .with_case_insensitive_names(true);
+    for name in ["foo", "bar", "baz"] {
+        cli_migrator.add_migration(Box::new(Tagged(name, &[])))?;
+    }
+    promad::cli::PromadCli::parse_from(["promad", "apply", "--atomic", "BAZ"])
+        .run(cli_migrator)
+        .await?;
+    assert!(migrator.is_applied("baz").await?);
+
+    sqlx::query("CREATE TABLE foo (id INTEGER)")
+        .execute(&pool)
+        .await?;
+    migrator.revert_all().await?;
+    assert!(repo.get_all(&mut conn).await?.is_empty());
+    Ok(())
+}
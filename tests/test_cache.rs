@@ -0,0 +1,284 @@
+#![cfg(feature = "postgres")]
+
+use std::{cell::Cell, error::Error};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use promad::{
+    repo::{
+        memory::MemoryPromadRepo, postgres::PostgresPromadRepo, CachedPromadRepo, PromadRepo,
+        PromadRow,
+    },
+    Migrator, NullMigrationUI, SqlFileMigration,
+};
+use sqlx::{Database, Postgres};
+
+mod common;
+
+use common::*;
+
+thread_local! {
+    static GET_ALL_CALLS: Cell<usize> = const { Cell::new(0) };
+    static GET_CALLS: Cell<usize> = const { Cell::new(0) };
+    static INSERT_CALLS: Cell<usize> = const { Cell::new(0) };
+    static INSERT_MANY_CALLS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// In-memory repo that counts how often the cache falls through to it.
+struct CountingRepo(MemoryPromadRepo<Postgres>);
+
+#[async_trait]
+impl PromadRepo<Postgres> for CountingRepo {
+    fn new() -> Self {
+        Self(MemoryPromadRepo::new())
+    }
+
+    async fn init<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<()> {
+        self.0.init(conn).await
+    }
+
+    async fn set_read_only<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<()> {
+        self.0.set_read_only(conn).await
+    }
+
+    async fn get_all<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<Vec<PromadRow>> {
+        GET_ALL_CALLS.with(|x| x.set(x.get() + 1));
+        self.0.get_all(conn).await
+    }
+
+    async fn get<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<Option<PromadRow>> {
+        GET_CALLS.with(|x| x.set(x.get() + 1));
+        self.0.get(name, conn).await
+    }
+
+    async fn insert<'a>(
+        &self,
+        row: &PromadRow,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<bool> {
+        INSERT_CALLS.with(|x| x.set(x.get() + 1));
+        self.0.insert(row, conn).await
+    }
+
+    async fn insert_many<'a>(
+        &self,
+        rows: &[PromadRow],
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<()> {
+        INSERT_MANY_CALLS.with(|x| x.set(x.get() + 1));
+        self.0.insert_many(rows, conn).await
+    }
+
+    async fn clear<'a>(
+        &self,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<()> {
+        self.0.clear(conn).await
+    }
+
+    async fn delete<'a>(
+        &self,
+        name: &str,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<()> {
+        self.0.delete(name, conn).await
+    }
+
+    fn set_sql_logging(&mut self, enabled: bool) {
+        self.0.set_sql_logging(enabled);
+    }
+
+    fn set_server_side_timestamps(&mut self, enabled: bool) {
+        self.0.set_server_side_timestamps(enabled);
+    }
+
+    async fn update_checksum<'a>(
+        &self,
+        name: &str,
+        checksum: Option<&str>,
+        conn: &'a mut <Postgres as Database>::Connection,
+    ) -> promad::error::Result<()> {
+        self.0.update_checksum(name, checksum, conn).await
+    }
+}
+
+fn calls() -> (usize, usize) {
+    (GET_ALL_CALLS.with(Cell::get), GET_CALLS.with(Cell::get))
+}
+
+#[tokio::test]
+async fn test_cache_coherence() -> Result<(), Box<dyn Error>> {
+    let env = make_test_harness().await?;
+    let mut conn = env.pool.acquire().await?;
+    let repo = CachedPromadRepo::<Postgres, CountingRepo>::new();
+    repo.init(&mut conn).await?;
+
+    let row = PromadRow {
+        name: "migration1".to_string(),
+        ordering_key: 0,
+        created_at: Utc::now(),
+        checksum: None,
+        applied_by: None,
+    };
+    assert!(repo.insert(&row, &mut conn).await?);
+
+    // Nothing is loaded yet, so single lookups go to the database every time.
+    assert!(repo.get("migration1", &mut conn).await?.is_some());
+    assert!(repo.get("missing", &mut conn).await?.is_none());
+    assert_eq!(calls(), (0, 2));
+
+    // The first `get_all` loads the table, after which everything is cached.
+    assert_eq!(repo.get_all(&mut conn).await?.len(), 1);
+    assert_eq!(repo.get_all(&mut conn).await?.len(), 1);
+    assert!(repo.get("migration1", &mut conn).await?.is_some());
+    assert!(repo.get("missing", &mut conn).await?.is_none());
+    assert_eq!(calls(), (1, 2));
+
+    // Writes keep the loaded cache up to date.
+    let row2 = PromadRow {
+        name: "migration2".to_string(),
+        ordering_key: 1,
+        created_at: Utc::now(),
+        checksum: None,
+        applied_by: None,
+    };
+    repo.insert(&row2, &mut conn).await?;
+    assert_eq!(repo.get_all(&mut conn).await?.len(), 2);
+    repo.delete("migration1", &mut conn).await?;
+    assert!(repo.get("migration1", &mut conn).await?.is_none());
+    assert_eq!(calls(), (1, 2));
+
+    // Invalidating forces the next read back to the database.
+    repo.invalidate()?;
+    let rows = repo.get_all(&mut conn).await?;
+    assert_eq!(
+        rows.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+        vec!["migration2"]
+    );
+    assert_eq!(calls(), (2, 2));
+
+    // Recording a migration twice is a no-op, and the cache is reloaded in
+    // case another process recorded it.
+    let duplicate = PromadRow {
+        ordering_key: 2,
+        ..row2
+    };
+    assert!(!repo.insert(&duplicate, &mut conn).await?);
+    assert_eq!(repo.get_all(&mut conn).await?.len(), 1);
+    assert_eq!(calls(), (3, 2));
+
+    // Uncached lookups go to the database but keep the cache loaded.
+    assert!(repo.get_uncached("migration2", &mut conn).await?.is_some());
+    assert_eq!(repo.get_all(&mut conn).await?.len(), 1);
+    assert_eq!(calls(), (3, 3));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_insert_many() -> Result<(), Box<dyn Error>> {
+    let env = make_test_harness().await?;
+    let mut conn = env.pool.acquire().await?;
+    let repo = CachedPromadRepo::<Postgres, PostgresPromadRepo>::new();
+    repo.init(&mut conn).await?;
+    assert!(repo.get_all(&mut conn).await?.is_empty());
+
+    let rows = (0..3)
+        .map(|x| PromadRow {
+            name: format!("migration{x}"),
+            ordering_key: x,
+            created_at: Utc::now(),
+            checksum: None,
+            applied_by: None,
+        })
+        .collect::<Vec<_>>();
+    repo.insert_many(&rows, &mut conn).await?;
+    repo.insert_many(&[], &mut conn).await?;
+
+    // Both the cache and the table have all of them.
+    assert_eq!(repo.get_all(&mut conn).await?.len(), 3);
+    assert_eq!(repo.get_all_uncached(&mut conn).await?.len(), 3);
+
+    // Recording them again, as a process that lost a race would, leaves the
+    // rows that were there first.
+    let again = rows
+        .iter()
+        .map(|x| PromadRow {
+            ordering_key: x.ordering_key + 10,
+            ..x.clone()
+        })
+        .collect::<Vec<_>>();
+    repo.insert_many(&again, &mut conn).await?;
+    let stored = repo.get_all_uncached(&mut conn).await?;
+    assert_eq!(
+        stored.iter().map(|x| x.ordering_key).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_runtime_name() -> Result<(), Box<dyn Error>> {
+    let env = make_test_harness().await?;
+    let mut conn = env.pool.acquire().await?;
+    let repo = CachedPromadRepo::<Postgres, PostgresPromadRepo>::new();
+    repo.init(&mut conn).await?;
+
+    let row = PromadRow {
+        name: format!("migration{}", 1),
+        ordering_key: 0,
+        created_at: Utc::now(),
+        checksum: None,
+        applied_by: None,
+    };
+    repo.insert(&row, &mut conn).await?;
+    assert_eq!(repo.get_all(&mut conn).await?.len(), 1);
+
+    // Names don't have to be `'static` to be deleted.
+    let name = row.name.clone();
+    repo.delete(&name, &mut conn).await?;
+    assert!(repo.get_all(&mut conn).await?.is_empty());
+    assert!(repo.get_all_uncached(&mut conn).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_apply_all_in_inserts_once() -> Result<(), Box<dyn Error>> {
+    let env = make_test_harness().await?;
+    let mut migrator = Migrator::create_with_repo(
+        env.pool.clone(),
+        Box::new(CountingRepo::new()),
+        Box::new(|_| Box::new(NullMigrationUI)),
+    );
+    for name in ["a", "b", "c"] {
+        migrator.add_migration(Box::new(SqlFileMigration::new(
+            name,
+            "SELECT 1",
+            Some("SELECT 1"),
+        )))?;
+    }
+
+    let mut txn = env.pool.begin().await?;
+    migrator.apply_all_in(&mut txn).await?;
+    txn.commit().await?;
+
+    // The three tracking rows are written by a single batch insert.
+    assert_eq!(INSERT_CALLS.with(Cell::get), 0);
+    assert_eq!(INSERT_MANY_CALLS.with(Cell::get), 1);
+    assert_eq!(migrator.pending_count().await?, 0);
+    Ok(())
+}
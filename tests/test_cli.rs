@@ -0,0 +1,205 @@
+use std::{error::Error, io::Write};
+
+use clap::Parser;
+use promad::cli::{CliConfig, CliLock, PromadCli};
+
+#[test]
+fn test_config_precedence() -> Result<(), Box<dyn Error>> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    writeln!(file, "# Written by the deploy scripts")?;
+    writeln!(file, "PROMAD_DATABASE_URL=\"postgres://file\"")?;
+    writeln!(file, "PROMAD_TABLE_NAME=file_table")?;
+    writeln!(file, "PROMAD_LOCK=none")?;
+    assert_eq!(
+        CliConfig::from_file(file.path())?,
+        CliConfig {
+            database_url: Some("postgres://file".to_string()),
+            table_name: Some("file_table".to_string()),
+            lock: Some(CliLock::None),
+        }
+    );
+
+    let env = std::collections::HashMap::from([
+        ("PROMAD_TABLE_NAME", "env_table"),
+        ("PROMAD_LOCK", "advisory"),
+    ]);
+    let var = |key: &str| env.get(key).map(|x| x.to_string());
+    let cli = PromadCli::parse_from([
+        "promad",
+        "list",
+        "--lock",
+        "none",
+        "--config",
+        file.path().to_str().unwrap(),
+    ]);
+    assert_eq!(
+        cli.config_with_vars(var)?,
+        CliConfig {
+            database_url: Some("postgres://file".to_string()),
+            table_name: Some("env_table".to_string()),
+            lock: Some(CliLock::None),
+        }
+    );
+
+    assert!(matches!(
+        CliConfig::from_vars(|key| (key == "PROMAD_LOCK").then(|| "sometimes".to_string())),
+        Err(promad::error::Error::ConfigError(_))
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_run_rejects_database_url() -> Result<(), Box<dyn Error>> {
+    let pool = sqlx::postgres::PgPoolOptions::new().connect_lazy("postgres://localhost")?;
+    let migrator = promad::Migrator::create(pool);
+    let cli = PromadCli::parse_from(["promad", "--database-url", "postgres://other", "list"]);
+    assert!(matches!(
+        cli.run(migrator).await,
+        Err(promad::error::Error::ConfigError(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_no_validate_flag() {
+    assert!(!PromadCli::parse_from(["promad", "apply"]).no_validate);
+    assert!(PromadCli::parse_from(["promad", "apply", "--no-validate"]).no_validate);
+}
+
+#[test]
+fn test_list_format() {
+    use promad::cli::{ListFormat, PromadSubcommand};
+
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "list"]).subcmd,
+        PromadSubcommand::List {
+            format: ListFormat::Table
+        }
+    ));
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "list", "--format", "csv"]).subcmd,
+        PromadSubcommand::List {
+            format: ListFormat::Csv
+        }
+    ));
+}
+
+#[test]
+fn test_migrations_dir_flag() {
+    assert_eq!(
+        PromadCli::parse_from(["promad", "apply"]).migrations_dir,
+        None
+    );
+    assert_eq!(
+        PromadCli::parse_from(["promad", "--migrations-dir", "./sql", "apply"]).migrations_dir,
+        Some("./sql".into())
+    );
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_run_unsupported_scheme() {
+    for (url, scheme) in [
+        ("mysql://localhost/app", "mysql"),
+        ("sqlite:app.db", "sqlite"),
+    ] {
+        let cli = PromadCli::parse_from(["promad", "list"]);
+        assert!(matches!(
+            promad::cli::run(cli, url, vec![]).await,
+            Err(promad::error::Error::UnsupportedDatabaseScheme(x)) if x == scheme
+        ));
+    }
+}
+
+#[cfg(any(feature = "postgres", feature = "any"))]
+#[tokio::test]
+async fn test_any_migrator_unsupported_scheme() {
+    // MySQL goes through `sqlx::Any` with the feature on.
+    #[cfg(not(feature = "any"))]
+    assert!(matches!(
+        promad::cli::AnyMigrator::connect("mysql://localhost/app").await,
+        Err(promad::error::Error::UnsupportedDatabaseScheme(x)) if x == "mysql"
+    ));
+    assert!(matches!(
+        promad::cli::AnyMigrator::connect("mssql://localhost/app").await,
+        Err(promad::error::Error::UnsupportedDatabaseScheme(x)) if x == "mssql"
+    ));
+}
+
+#[test]
+fn test_atomic_flag() {
+    use promad::cli::PromadSubcommand;
+
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "apply", "--atomic", "--steps", "2"]).subcmd,
+        PromadSubcommand::Apply {
+            name: None,
+            steps: Some(2),
+            atomic: true,
+            until: None,
+            fake: false
+        }
+    ));
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "apply"]).subcmd,
+        PromadSubcommand::Apply { atomic: false, .. }
+    ));
+}
+
+#[test]
+fn test_until_flag() {
+    use promad::cli::PromadSubcommand;
+
+    let until = "2024-01-01T00:00:00Z"
+        .parse::<chrono::DateTime<chrono::Utc>>()
+        .unwrap();
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "apply", "--until", "2024-01-01T00:00:00Z"]).subcmd,
+        PromadSubcommand::Apply { until: Some(x), .. } if x == until
+    ));
+    assert!(PromadCli::try_parse_from(["promad", "apply", "--until", "yesterday"]).is_err());
+    assert!(PromadCli::try_parse_from([
+        "promad",
+        "apply",
+        "--steps",
+        "1",
+        "--until",
+        "2024-01-01T00:00:00Z"
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_fake_flag() {
+    use promad::cli::PromadSubcommand;
+
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "apply", "--fake", "migration3"]).subcmd,
+        PromadSubcommand::Apply { name: Some(name), fake: true, .. } if name == "migration3"
+    ));
+    // Only a single named migration can be faked.
+    assert!(PromadCli::try_parse_from(["promad", "apply", "--fake"]).is_err());
+    assert!(
+        PromadCli::try_parse_from(["promad", "apply", "--fake", "--atomic", "migration3"]).is_err()
+    );
+}
+
+#[test]
+fn test_reset_flags() {
+    use promad::cli::PromadSubcommand;
+
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "reset", "--yes", "--no-revert"]).subcmd,
+        PromadSubcommand::Reset {
+            yes: true,
+            no_revert: true,
+            force: false
+        }
+    ));
+    assert!(matches!(
+        PromadCli::parse_from(["promad", "reset"]).subcmd,
+        PromadSubcommand::Reset { yes: false, .. }
+    ));
+}
@@ -0,0 +1,63 @@
+#![cfg(all(feature = "include_dir", feature = "postgres"))]
+
+use std::error::Error;
+
+use include_dir::{include_dir, Dir};
+use promad::repo::PromadRepo;
+
+mod common;
+
+use common::*;
+
+static MIGRATIONS: Dir = include_dir!("$CARGO_MANIFEST_DIR/tests/migrations/embedded");
+static ORPHAN_DOWN: Dir = include_dir!("$CARGO_MANIFEST_DIR/tests/migrations/orphan_down");
+
+#[tokio::test]
+async fn test_embedded_migrations() -> Result<(), Box<dyn Error>> {
+    let mut env = make_test_harness().await?;
+    env.migrator.add_migrations_from_embedded(&MIGRATIONS)?;
+    assert_eq!(env.migrator.migration_count(), 3);
+    env.migrator.apply_all().await?;
+
+    // Ordered by number rather than by file name.
+    let mut conn = env.pool.acquire().await?;
+    let rows = env.repo.get_all(&mut conn).await?;
+    assert_eq!(
+        rows.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+        vec!["1_create_users", "2_add_email", "10_create_posts"]
+    );
+    sqlx::query("SELECT id, email FROM users")
+        .execute(conn.as_mut())
+        .await?;
+
+    // Without a down script the last migration can't be reverted.
+    assert!(matches!(
+        env.migrator.revert_all().await,
+        Err(promad::error::Error::IrreversibleMigration(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedded_orphan_down() -> Result<(), Box<dyn Error>> {
+    let mut env = make_test_harness().await?;
+    assert!(matches!(
+        env.migrator.add_migrations_from_embedded(&ORPHAN_DOWN),
+        Err(promad::error::Error::InvalidMigrationFile { .. })
+    ));
+    assert_eq!(env.migrator.migration_count(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedded_source() -> Result<(), Box<dyn Error>> {
+    use promad::MigrationSource;
+
+    let migrations = promad::EmbeddedSource::new(&MIGRATIONS).load().await?;
+    assert_eq!(migrations.len(), 3);
+    assert!(promad::EmbeddedSource::new(&ORPHAN_DOWN)
+        .load()
+        .await
+        .is_err());
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use promad::error::Error;
+
+fn io_error() -> sqlx::Error {
+    sqlx::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+}
+
+#[test]
+fn test_connection_error() {
+    assert!(Error::DatabaseError(io_error()).is_connection_error());
+    assert!(Error::DatabaseError(sqlx::Error::PoolClosed).is_connection_error());
+    assert!(!Error::DatabaseError(sqlx::Error::RowNotFound).is_connection_error());
+    assert!(!Error::NoSuchMigration("migration1".into()).is_connection_error());
+}
+
+#[test]
+fn test_wrapped_sqlx_error() {
+    let e = Error::MigrationFailed {
+        name: "migration1".into(),
+        direction: promad::Direction::Up,
+        source: io_error(),
+    };
+    assert!(matches!(e.as_sqlx_error(), Some(sqlx::Error::Io(_))));
+    assert!(e.is_connection_error());
+    assert!(e.as_database_error().is_none());
+    assert!(!e.is_unique_violation());
+}
+
+#[test]
+fn test_exit_code() {
+    assert_eq!(Error::PoolRequired.exit_code(), 1);
+    assert_eq!(
+        Error::HistoryMigrationMismatch {
+            remote_name: "migration1".into(),
+            local_name: "migration2".into(),
+        }
+        .exit_code(),
+        2
+    );
+    assert_eq!(Error::NoSuchMigration("migration1".into()).exit_code(), 3);
+    assert_eq!(Error::DatabaseError(io_error()).exit_code(), 4);
+    assert_eq!(Error::LockUnavailable.exit_code(), 5);
+    assert_eq!(Error::Interrupted { completed: vec![] }.exit_code(), 130);
+}
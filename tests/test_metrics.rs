@@ -0,0 +1,50 @@
+#![cfg(all(feature = "metrics", feature = "postgres"))]
+
+use std::error::Error;
+
+use metrics_util::{
+    debugging::{DebugValue, DebuggingRecorder},
+    MetricKind,
+};
+use promad::*;
+
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn test_metrics() -> Result<(), Box<dyn Error>> {
+    let recorder = DebuggingRecorder::per_thread();
+    let snapshotter = recorder.snapshotter();
+    recorder.install()?;
+
+    let migration = create_migration!(
+        TestMigration,
+        "test_migration",
+        "CREATE TABLE test (id INT PRIMARY KEY)",
+        "DROP TABLE test"
+    );
+    let mut env = make_test_harness().await?;
+    env.migrator.add_migration(migration())?;
+    assert_eq!(env.migrator.pending_count().await?, 1);
+    env.migrator.apply_all().await?;
+
+    let metrics = snapshotter.snapshot().into_vec();
+    let find = |kind, name: &str| {
+        metrics
+            .iter()
+            .find(|(key, ..)| key.kind() == kind && key.key().name() == name)
+            .map(|(key, _, _, value)| (key, value))
+    };
+    let (_, pending) = find(MetricKind::Gauge, "promad.migrations.pending").unwrap();
+    assert_eq!(pending, &DebugValue::Gauge(1.0.into()));
+    let (applied_key, applied) = find(MetricKind::Counter, "promad.migrations.applied").unwrap();
+    assert_eq!(applied, &DebugValue::Counter(1));
+    assert!(applied_key
+        .key()
+        .labels()
+        .any(|x| x.key() == "name" && x.value() == "test_migration"));
+    let (_, duration) = find(MetricKind::Histogram, "promad.migration.duration_seconds").unwrap();
+    assert!(matches!(duration, DebugValue::Histogram(x) if x.len() == 1));
+    Ok(())
+}
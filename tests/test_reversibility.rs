@@ -0,0 +1,44 @@
+#![cfg(all(feature = "testing", feature = "postgres"))]
+
+use std::error::Error;
+
+use promad::*;
+
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn test_reversibility() -> Result<(), Box<dyn Error>> {
+    let migration1 = create_migration!(
+        Migration1,
+        "migration1",
+        "CREATE TABLE test1 (id INT PRIMARY KEY)",
+        "DROP TABLE test1"
+    );
+    let migration2 = create_migration!(
+        Migration2,
+        "migration2",
+        "ALTER TABLE test1 ADD COLUMN name TEXT; CREATE INDEX test1_name ON test1 (name)",
+        "ALTER TABLE test1 DROP COLUMN name"
+    );
+    let migration3 = create_migration!(
+        Migration3,
+        "migration3",
+        "CREATE INDEX test1_id ON test1 (id)",
+        "SELECT 1"
+    );
+    let mut env = make_test_harness().await?;
+    env.migrator.add_migration(migration1())?;
+    env.migrator.add_migration(migration2())?;
+    env.migrator.add_migration(migration3())?;
+
+    let results = env.migrator.test_reversibility().await?;
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_symmetric());
+    // Dropping the column takes its index with it.
+    assert!(results[1].is_symmetric());
+    assert!(!results[2].is_symmetric());
+    assert!(results[2].differences[0].starts_with("+index public.test1_id"));
+    Ok(())
+}
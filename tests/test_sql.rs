@@ -0,0 +1,185 @@
+#[cfg(feature = "postgres")]
+use std::error::Error;
+
+use promad::sql::split_statements;
+#[cfg(feature = "postgres")]
+use promad::*;
+
+#[cfg(feature = "postgres")]
+mod common;
+
+#[cfg(feature = "postgres")]
+use common::*;
+
+#[test]
+fn test_split_statements() {
+    assert_eq!(
+        split_statements("CREATE TABLE a (id INT); CREATE TABLE b (id INT)"),
+        vec!["CREATE TABLE a (id INT)", "CREATE TABLE b (id INT)"]
+    );
+
+    // Trailing semicolons, blank and comment only statements are dropped.
+    assert_eq!(
+        split_statements("SELECT 1;;\n  ;\n-- done;\n/* really; done */"),
+        vec!["SELECT 1"]
+    );
+
+    // Semicolons in literals, identifiers and comments don't split.
+    assert_eq!(
+        split_statements(
+            "INSERT INTO t VALUES ('a;b', 'it''s;'); -- trailing; comment\n\
+             SELECT \"odd;name\" FROM t /* nested /* ; */ ; */; SELECT E'\\';'"
+        ),
+        vec![
+            "INSERT INTO t VALUES ('a;b', 'it''s;')",
+            "-- trailing; comment\nSELECT \"odd;name\" FROM t /* nested /* ; */ ; */",
+            "SELECT E'\\';'",
+        ]
+    );
+}
+
+#[test]
+fn test_split_dollar_quoted() {
+    let function = "CREATE FUNCTION f() RETURNS INT AS $body$\n\
+                    BEGIN\n    PERFORM 1; RETURN $$;$$::INT;\nEND;\n$body$ LANGUAGE plpgsql";
+    assert_eq!(
+        split_statements(&format!("{function};\nSELECT f();")),
+        vec![function, "SELECT f()"]
+    );
+
+    // Parameters aren't dollar quotes.
+    assert_eq!(
+        split_statements("SELECT $1; SELECT $2"),
+        vec!["SELECT $1", "SELECT $2"]
+    );
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_multi_statement_migration() -> Result<(), Box<dyn Error>> {
+    let migration = create_migration!(
+        TestMigration,
+        "test_migration",
+        "CREATE TABLE test (id INT PRIMARY KEY, note TEXT);
+         INSERT INTO test VALUES (1, 'one; two');
+         CREATE FUNCTION test_count() RETURNS BIGINT AS $$
+             SELECT count(*) FROM test;
+         $$ LANGUAGE sql;",
+        "DROP FUNCTION test_count(); DROP TABLE test;"
+    );
+    let mut env = make_test_harness().await?;
+    env.migrator.add_migration(migration())?;
+    env.migrator.apply_all().await?;
+
+    let mut conn = env.pool.acquire().await?;
+    let (count,): (i64,) = sqlx::query_as("SELECT test_count()")
+        .fetch_one(conn.as_mut())
+        .await?;
+    assert_eq!(count, 1);
+
+    env.migrator.revert_all().await?;
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_render() -> Result<(), Box<dyn Error>> {
+    // Rendering never touches the database.
+    let pool = sqlx::PgPool::connect_lazy("postgres://localhost/promad")?;
+    let mut migrator = Migrator::create_with_ui(pool, Box::new(|_| Box::<MockUI>::default()));
+    migrator.add_migrations(vec![
+        Box::new(SqlFileMigration::new(
+            "create_users",
+            "CREATE TABLE users (id INT);",
+            Some("DROP TABLE users;"),
+        )),
+        Box::new(SqlFileMigration::new(
+            "drop_legacy",
+            "DROP TABLE legacy;",
+            None::<&str>,
+        )),
+        create_migration!(TestMigration, "rust_migration", "SELECT 1", "SELECT 1")(),
+    ])?;
+
+    assert_eq!(
+        migrator.render("create_users")?,
+        RenderedMigration {
+            up_sql: "CREATE TABLE users (id INT);".to_string(),
+            down_sql: Some("DROP TABLE users;".to_string()),
+        }
+    );
+    assert_eq!(migrator.render("drop_legacy")?.down_sql, None);
+    assert!(matches!(
+        migrator.render("rust_migration"),
+        Err(error::Error::NotRenderable(_))
+    ));
+    assert!(matches!(
+        migrator.render("missing"),
+        Err(error::Error::NoSuchMigration(_))
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_migrations_from_dir() -> Result<(), Box<dyn Error>> {
+    // Loading never touches the database.
+    let pool = sqlx::PgPool::connect_lazy("postgres://localhost/promad")?;
+    let mut migrator = Migrator::create_with_ui(pool, Box::new(|_| Box::<MockUI>::default()));
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/migrations");
+    migrator.add_migrations_from_dir(&dir.join("embedded"))?;
+    assert_eq!(migrator.migration_count(), 3);
+    assert!(migrator.render("1_create_users")?.down_sql.is_some());
+    assert_eq!(migrator.render("10_create_posts")?.down_sql, None);
+
+    assert!(matches!(
+        migrator.add_migrations_from_dir(&dir.join("orphan_down")),
+        Err(error::Error::InvalidMigrationFile { .. })
+    ));
+    assert!(matches!(
+        migrator.add_migrations_from_dir(&dir.join("missing")),
+        Err(error::Error::InvalidMigrationFile { .. })
+    ));
+    Ok(())
+}
+
+/// Stands in for a loader that fetches migrations from remote storage.
+#[cfg(feature = "postgres")]
+struct StaticSource;
+
+#[cfg(feature = "postgres")]
+#[async_trait::async_trait]
+impl MigrationSource for StaticSource {
+    async fn load(&self) -> promad::error::Result<Vec<SqlFileMigration>> {
+        Ok(vec![SqlFileMigration::new(
+            "1_remote",
+            "CREATE TABLE remote (id INT)",
+            Some("DROP TABLE remote"),
+        )])
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_migrations_from_source() -> Result<(), Box<dyn Error>> {
+    let pool = sqlx::PgPool::connect_lazy("postgres://localhost/promad")?;
+    let mut migrator = Migrator::create_with_ui(pool, Box::new(|_| Box::<MockUI>::default()));
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/migrations");
+    migrator
+        .add_migrations_from_source(&FsSource::new(dir.join("embedded")))
+        .await?;
+    migrator.add_migrations_from_source(&StaticSource).await?;
+    assert_eq!(migrator.migration_count(), 4);
+    assert_eq!(
+        migrator.render("1_remote")?.up_sql,
+        "CREATE TABLE remote (id INT)"
+    );
+
+    assert!(matches!(
+        migrator
+            .add_migrations_from_source(&FsSource::new(dir.join("missing")))
+            .await,
+        Err(error::Error::InvalidMigrationFile { .. })
+    ));
+    Ok(())
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "postgres")]
+
+use promad::{error::Error, InteractiveMigrationUI, Migration, PlainMigrationUI};
+use sqlx::Postgres;
+
+#[test]
+fn test_second_interactive_ui_degrades() {
+    let migrations: &[(i64, &dyn Migration<Postgres>)] = &[];
+
+    let first = InteractiveMigrationUI::try_new(migrations).unwrap();
+    assert!(matches!(
+        InteractiveMigrationUI::try_new(migrations),
+        Err(Error::StdoutRedirectHeld)
+    ));
+    // The infallible constructor hands back a UI that doesn't redirect.
+    let second = InteractiveMigrationUI::boxed(migrations);
+    second.complete(&promad::RunSummary {
+        count: 0,
+        direction: promad::Direction::Up,
+        elapsed: std::time::Duration::ZERO,
+    });
+    drop(second);
+    let _plain = PlainMigrationUI::new(migrations);
+
+    // Once the first is gone, the redirect is free again.
+    drop(first);
+    assert!(InteractiveMigrationUI::try_new(migrations).is_ok());
+
+    // Styling doesn't change how the redirect is handed out.
+    let config = promad::InteractiveUiConfig {
+        style: promad::ProgressStyle::default_spinner().tick_chars("|/-\\ "),
+        tick_interval: std::time::Duration::from_millis(250),
+        ..Default::default()
+    };
+    let styled = InteractiveMigrationUI::boxed_with_config(migrations, &config);
+    assert!(matches!(
+        InteractiveMigrationUI::try_new(migrations),
+        Err(Error::StdoutRedirectHeld)
+    ));
+    drop(styled);
+}